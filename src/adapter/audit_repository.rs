@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use log::{debug, error};
+
+use crate::adapter::{open_configured_storage, AuditEntryEntity, BotStorage, JsonFileStorage};
+
+/// Filter of an audit log query, unset fields don't restrict the result.
+#[derive(Clone, Default, Debug)]
+pub struct AuditQuery {
+    /// Matches entries where the user is either actor or target, a leading `@` is ignored.
+    pub user: Option<String>,
+    pub since_unix_time: Option<u64>,
+    pub until_unix_time: Option<u64>,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntryEntity) -> bool {
+        let normalize = |name: &str| name.trim_start_matches('@').to_lowercase();
+        let is_user_matching = self.user.as_ref().is_none_or(|user| {
+            let user = normalize(user);
+            normalize(&entry.actor) == user || normalize(&entry.target) == user
+        });
+        is_user_matching
+            && self
+                .since_unix_time
+                .is_none_or(|since| entry.created_unix_time >= since)
+            && self
+                .until_unix_time
+                .is_none_or(|until| entry.created_unix_time <= until)
+    }
+}
+
+/// Append-only log of the moderation actions taken by admins and by the LLM.
+#[derive(Clone)]
+pub struct AuditRepository {
+    storage: Arc<dyn BotStorage>,
+}
+
+impl Default for AuditRepository {
+    fn default() -> Self {
+        Self {
+            storage: Arc::new(JsonFileStorage::from_env()),
+        }
+    }
+}
+
+impl AuditRepository {
+    pub fn try_init() -> Self {
+        Self {
+            storage: open_configured_storage(),
+        }
+    }
+
+    pub fn record(&self, entry: AuditEntryEntity) {
+        debug!(
+            "Audit chat {}: {} {} {} -> {}",
+            entry.chat_id, entry.actor, entry.action, entry.target, entry.result
+        );
+        if let Err(e) = self.storage.append_audit(&entry) {
+            error!("Could not write audit entry cause: {}", e);
+        }
+    }
+
+    /// Returns the matching entries of a chat, oldest entry first.
+    pub fn query(&self, chat_id: &str, query: &AuditQuery) -> Vec<AuditEntryEntity> {
+        match self.storage.load_audit(chat_id) {
+            Ok(entries) => entries
+                .into_iter()
+                .filter(|entry| query.matches(entry))
+                .collect(),
+            Err(e) => {
+                error!("Could not read audit log of chat {} cause: {}", chat_id, e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod audit_repository_test {
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use crate::adapter::{
+        test_directory::TestDirectory, AuditEntryEntity, AuditQuery, AuditRepository,
+        JsonFileStorage,
+    };
+
+    #[test]
+    fn should_filter_audit_entries_by_user_and_time() {
+        let directory = TestDirectory::new("audit");
+        let storage = JsonFileStorage::new(&directory.database_file());
+        let audit_repository = AuditRepository {
+            storage: Arc::new(storage),
+        };
+
+        let mut old_entry = AuditEntryEntity::new(
+            "-100",
+            "@admin",
+            "kevin",
+            "mute",
+            json!({ "mute_time": 600 }),
+            Some("spam".to_string()),
+            "success",
+        );
+        old_entry.created_unix_time = 10;
+        audit_repository.record(old_entry);
+        audit_repository.record(AuditEntryEntity::new(
+            "-100",
+            "LLM",
+            "Kevin",
+            "kick_user_from_chat",
+            json!({ "name": "Kevin" }),
+            None,
+            "success",
+        ));
+        audit_repository.record(AuditEntryEntity::new(
+            "-200",
+            "LLM",
+            "kevin",
+            "mute",
+            json!({}),
+            None,
+            "success",
+        ));
+
+        let by_user = audit_repository.query(
+            "-100",
+            &AuditQuery {
+                user: Some("@kevin".to_string()),
+                ..Default::default()
+            },
+        );
+        let since = audit_repository.query(
+            "-100",
+            &AuditQuery {
+                since_unix_time: Some(100),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(by_user.len(), 2);
+        assert_eq!(since.len(), 1);
+        assert_eq!(since[0].actor, "LLM");
+    }
+}
@@ -1,16 +1,51 @@
-use std::{
-    collections::HashMap,
-    env,
-    error::Error,
-    fs::File,
-    io::{BufReader, BufWriter, Write},
-    path::Path,
-};
+use std::{collections::HashMap, error::Error, sync::Arc, time::SystemTime};
 
+use ollama_rs::generation::chat::ChatMessage;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::adapter::{
+    open_configured_storage, BotStorage, JsonFileStorage, WriteBehindFlusher,
+    CURRENT_SCHEMA_VERSION,
+};
 
-const FILE_NAME: &str = "bot_db.json";
-const FILE_DB_PATH_ENV: &str = "FILE_DB_PATH";
+pub const SANCTION_KINDS: [&str; 3] = ["warning", "mute", "kick"];
+
+/// A warning, mute or kick a member has received.
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct SanctionEntity {
+    pub created_unix_time: u64,
+    pub kind: String,
+    pub actor: String,
+    pub reason: Option<String>,
+    pub result: String,
+}
+
+impl SanctionEntity {
+    pub fn new(
+        created_unix_time: u64,
+        kind: &str,
+        actor: &str,
+        reason: Option<String>,
+        result: &str,
+    ) -> Self {
+        Self {
+            created_unix_time,
+            kind: kind.to_string(),
+            actor: actor.to_string(),
+            reason,
+            result: result.to_string(),
+        }
+    }
+}
+
+/// Username and first name a member used until `changed_unix_time`.
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct NameChangeEntity {
+    pub changed_unix_time: u64,
+    pub username: String,
+    pub firstname: String,
+}
 
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct UserEntity {
@@ -18,6 +53,16 @@ pub struct UserEntity {
     pub username: String,
     pub firstname: String,
     pub last_activity_unix_time: u64,
+    #[serde(default)]
+    pub first_seen_unix_time: u64,
+    #[serde(default)]
+    pub message_count: u64,
+    #[serde(default)]
+    pub topic_message_counts: HashMap<String, u64>,
+    #[serde(default)]
+    pub sanctions: Vec<SanctionEntity>,
+    #[serde(default)]
+    pub name_history: Vec<NameChangeEntity>,
 }
 
 impl UserEntity {
@@ -32,77 +77,262 @@ impl UserEntity {
             username: username.to_string(),
             firstname: firstname.to_string(),
             last_activity_unix_time,
+            first_seen_unix_time: last_activity_unix_time,
+            ..Default::default()
+        }
+    }
+
+    pub fn count_sanctions(&self, kind: &str) -> usize {
+        self.sanctions
+            .iter()
+            .filter(|sanction| sanction.kind == kind)
+            .count()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryEntryEntity {
+    pub created_unix_time: u64,
+    pub message: ChatMessage,
+    /// Marks the running summary of the messages compacted out of the history.
+    #[serde(default)]
+    pub summary: bool,
+}
+
+impl HistoryEntryEntity {
+    pub fn new(created_unix_time: u64, message: ChatMessage) -> Self {
+        Self {
+            created_unix_time,
+            message,
+            summary: false,
+        }
+    }
+
+    pub fn new_summary(created_unix_time: u64, message: ChatMessage) -> Self {
+        Self {
+            created_unix_time,
+            message,
+            summary: true,
+        }
+    }
+}
+
+/// A chat message or an admin-provided fact of the semantic memory, with its embedding.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MemoryEntryEntity {
+    pub created_unix_time: u64,
+    pub chat_id: String,
+    pub author: String,
+    /// `message` or `fact`
+    pub kind: String,
+    pub text: String,
+    pub embedding: Vec<f32>,
+}
+
+impl MemoryEntryEntity {
+    pub fn new(chat_id: &str, author: &str, kind: &str, text: &str, embedding: Vec<f32>) -> Self {
+        Self {
+            created_unix_time: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            chat_id: chat_id.to_string(),
+            author: author.to_string(),
+            kind: kind.to_string(),
+            text: text.to_string(),
+            embedding,
+        }
+    }
+}
+
+/// A single moderation action taken by an admin or by the LLM.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntryEntity {
+    pub created_unix_time: u64,
+    pub chat_id: String,
+    pub actor: String,
+    pub target: String,
+    pub action: String,
+    pub parameters: Value,
+    pub trigger_message: Option<String>,
+    pub result: String,
+}
+
+impl AuditEntryEntity {
+    pub fn new(
+        chat_id: &str,
+        actor: &str,
+        target: &str,
+        action: &str,
+        parameters: Value,
+        trigger_message: Option<String>,
+        result: &str,
+    ) -> Self {
+        Self {
+            created_unix_time: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            chat_id: chat_id.to_string(),
+            actor: actor.to_string(),
+            target: target.to_string(),
+            action: action.to_string(),
+            parameters,
+            trigger_message,
+            result: result.to_string(),
+        }
+    }
+}
+
+/// A moderation decision of the LLM with the reasoning of the model, looked up by the ids of
+/// the bot messages it resulted in.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DecisionEntity {
+    pub created_unix_time: u64,
+    pub chat_id: String,
+    pub message_ids: Vec<i64>,
+    pub trigger_message: Option<String>,
+    pub verdict: Value,
+    pub reasoning: Option<String>,
+}
+
+impl DecisionEntity {
+    pub fn new(
+        chat_id: &str,
+        message_ids: Vec<i64>,
+        trigger_message: Option<String>,
+        verdict: Value,
+        reasoning: Option<String>,
+    ) -> Self {
+        Self {
+            created_unix_time: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            chat_id: chat_id.to_string(),
+            message_ids,
+            trigger_message,
+            verdict,
+            reasoning,
         }
     }
 }
 
+/// Persona of the bot in a managed chat, unset fields fall back to the bot-wide settings.
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PersonaEntity {
+    pub name: Option<String>,
+    pub rules: Option<String>,
+    pub language: Option<String>,
+    pub strictness: Option<String>,
+    pub prompt_template: Option<String>,
+}
+
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
-pub struct BotMemoryEntity {
+pub struct ManagedChatEntity {
     pub user_map: HashMap<String, UserEntity>,
-    pub administrators: Vec<String>,
-    pub managed_chat_id: Option<String>,
+    /// Telegram user ids of the chat administrators.
+    pub administrators: Vec<i64>,
+    #[serde(default)]
+    pub persona: PersonaEntity,
+    /// Names of the forum topics by thread id.
+    #[serde(default)]
+    pub topic_names: HashMap<i64, String>,
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct BotMemoryEntity {
+    #[serde(default)]
+    pub version: u32,
+    pub managed_chats: HashMap<String, ManagedChatEntity>,
 }
 
-#[derive(Clone, Default)]
+impl BotMemoryEntity {
+    pub fn new() -> Self {
+        Self {
+            version: CURRENT_SCHEMA_VERSION,
+            managed_chats: HashMap::new(),
+        }
+    }
+}
+
+/// Bot memory backed by the configured storage. Within the tokio runtime writes are handed
+/// to the `WriteBehindFlusher`, otherwise they are written synchronously.
+#[derive(Clone)]
 pub struct BotDatabase {
     pub bot_memory: BotMemoryEntity,
+    storage: Arc<dyn BotStorage>,
+    flusher: Option<&'static WriteBehindFlusher>,
+}
+
+impl Default for BotDatabase {
+    fn default() -> Self {
+        Self {
+            bot_memory: BotMemoryEntity::new(),
+            storage: Arc::new(JsonFileStorage::from_env()),
+            flusher: None,
+        }
+    }
 }
 
 impl BotDatabase {
-    pub fn try_init() -> Self {
-        let file_path = env::var(FILE_DB_PATH_ENV).unwrap_or_else(|_| "./".to_string());
-
-        let result: Result<BotMemoryEntity, Box<dyn Error>> = {
-            let file_rs = File::open(format!("{}{}", &file_path, FILE_NAME));
-            match file_rs {
-                Ok(file) => {
-                    let reader = BufReader::new(file);
-                    let bot_memory = serde_json::from_reader(reader);
-                    if let Err(e) = bot_memory {
-                        panic!("Could not parse file cause: {}", e);
-                    }
-                    Ok(bot_memory.unwrap())
-                }
-                Err(e) => Err(format!("Could not open file cause: {}", e).into()),
-            }
-        };
+    #[cfg(test)]
+    pub fn with_storage(storage: Arc<dyn BotStorage>, bot_memory: BotMemoryEntity) -> Self {
+        Self {
+            bot_memory,
+            storage,
+            flusher: None,
+        }
+    }
 
-        if let Ok(rs) = result {
-            return Self { bot_memory: rs };
+    pub fn try_init() -> Self {
+        if let Some(flusher) = WriteBehindFlusher::running() {
+            return Self {
+                bot_memory: flusher.snapshot(),
+                storage: flusher.storage(),
+                flusher: Some(flusher),
+            };
         }
+        let storage = open_configured_storage();
 
-        let bot_mem = BotMemoryEntity {
-            user_map: HashMap::new(),
-            administrators: Vec::new(),
-            managed_chat_id: None,
+        let bot_memory = match storage.load() {
+            Ok(Some(bot_memory)) => bot_memory,
+            Ok(None) => {
+                let bot_mem = BotMemoryEntity::new();
+                if let Err(e) = storage.save(&bot_mem) {
+                    panic!("Could not create empty bot memory database cause: {}", e);
+                }
+                bot_mem
+            }
+            Err(e) => panic!("{}", e),
         };
 
-        if !Path::new(&format!("{}{}", file_path, FILE_NAME)).exists() {
-            let file_rs = File::create(format!("{}{}", file_path, FILE_NAME));
-            if let Err(e) = file_rs {
-                panic!("Could not create file cause: {}", e);
-            }
-            let file: File = file_rs.unwrap();
-            let mut writer = BufWriter::new(file);
-            if let Err(e) = serde_json::to_writer(&mut writer, &bot_mem) {
-                panic!("Could not create empty bot memory database cause: {}", e);
-            }
-            if let Err(e) = writer.flush() {
-                panic!("Could not write file cause: {}", e);
-            }
-        }
+        let flusher = WriteBehindFlusher::get_or_start(&storage, &bot_memory);
         Self {
-            bot_memory: bot_mem,
+            bot_memory,
+            storage,
+            flusher,
         }
     }
 
     pub fn save(&self) -> Result<(), Box<dyn Error>> {
-        let file_path = env::var(FILE_DB_PATH_ENV).unwrap_or_else(|_| "./".to_string());
-        let file = File::create(format!("{}{}", file_path, FILE_NAME))?;
-        let mut writer = BufWriter::new(file);
-        serde_json::to_writer(&mut writer, &self.bot_memory)?;
-        writer.flush()?;
+        match self.flusher {
+            Some(flusher) => {
+                flusher.save(&self.bot_memory);
+                Ok(())
+            }
+            None => self.storage.save(&self.bot_memory),
+        }
+    }
 
-        Ok(())
+    pub fn save_user(&self, chat_id: &str, user_id: i64) -> Result<(), Box<dyn Error>> {
+        let user_id = user_id.to_string();
+        match self.flusher {
+            Some(flusher) => {
+                flusher.save_user(&self.bot_memory, chat_id, &user_id);
+                Ok(())
+            }
+            None => self.storage.save_user(&self.bot_memory, chat_id, &user_id),
+        }
     }
 }
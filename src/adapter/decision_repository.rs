@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use log::{debug, error};
+
+use crate::adapter::{open_configured_storage, BotStorage, DecisionEntity, JsonFileStorage};
+
+/// Append-only log of the moderation decisions of the LLM, so admins can look up why the bot
+/// answered the way it did.
+#[derive(Clone)]
+pub struct DecisionRepository {
+    storage: Arc<dyn BotStorage>,
+}
+
+impl Default for DecisionRepository {
+    fn default() -> Self {
+        Self {
+            storage: Arc::new(JsonFileStorage::from_env()),
+        }
+    }
+}
+
+impl DecisionRepository {
+    pub fn try_init() -> Self {
+        Self {
+            storage: open_configured_storage(),
+        }
+    }
+
+    pub fn record(&self, entry: DecisionEntity) {
+        debug!(
+            "Decision chat {} messages {:?}: {}",
+            entry.chat_id, entry.message_ids, entry.verdict
+        );
+        if let Err(e) = self.storage.append_decision(&entry) {
+            error!("Could not write decision cause: {}", e);
+        }
+    }
+
+    /// Returns the decision which resulted in a bot message of a chat.
+    pub fn find_by_message(&self, chat_id: &str, message_id: i64) -> Option<DecisionEntity> {
+        match self.storage.load_decisions(chat_id) {
+            Ok(entries) => entries
+                .into_iter()
+                .rev()
+                .find(|entry| entry.message_ids.contains(&message_id)),
+            Err(e) => {
+                error!("Could not read decisions of chat {} cause: {}", chat_id, e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod decision_repository_test {
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use crate::adapter::{
+        test_directory::TestDirectory, DecisionEntity, DecisionRepository, JsonFileStorage,
+    };
+
+    #[test]
+    fn should_find_decision_by_bot_message() {
+        let directory = TestDirectory::new("decisions");
+        let storage = JsonFileStorage::new(&directory.database_file());
+        let decision_repository = DecisionRepository {
+            storage: Arc::new(storage),
+        };
+
+        decision_repository.record(DecisionEntity::new(
+            "-100",
+            vec![41, 42],
+            Some("Du Idiot".to_string()),
+            json!({ "action": "warn" }),
+            Some("Rule 4 forbids insults.".to_string()),
+        ));
+        decision_repository.record(DecisionEntity::new(
+            "-200",
+            vec![42],
+            None,
+            json!({ "action": "reply" }),
+            None,
+        ));
+
+        let decision = decision_repository.find_by_message("-100", 42).unwrap();
+
+        assert_eq!(decision.verdict["action"], "warn");
+        assert_eq!(
+            decision.reasoning.as_deref(),
+            Some("Rule 4 forbids insults.")
+        );
+        assert!(decision_repository.find_by_message("-100", 43).is_none());
+    }
+}
@@ -1,82 +1,49 @@
-use std::{
-    future::Future,
-    sync::{Arc, OnceLock},
-};
+use std::{sync::Arc, time::SystemTime};
 
 use crate::{
+    adapter::{
+        download_document, flush_pending_writes, send_document, AuditEntryEntity, AuditQuery,
+        AuditRepository, BotStateExport, DecisionEntity, DecisionRepository, ExportFormat,
+        PersonaEntity, SanctionEntity, StreamingReply, WorkQueue,
+    },
     application::{
-        self,
+        self, configured_locale,
         tools::{
             self, KICK_USER_WITHOUTBAN, KICK_USER_WITHOUTBAN_DESCRIPTION, MEMBER_INFO,
             MEMBER_INFO_DESCRIPTION, MESSAGE_REACTION, MESSAGE_REACTION_DESCRIPTION, MUTE_MEMBER,
             MUTE_MEMBER_DESCRIPTION, WEB_SEARCH, WEB_SEARCH_DESCRIPTION,
         },
-        ModeratorMessage, UserMessage,
+        DegradedModeFilter, Locale, ModerationVerdict, Text, UserMessage, VerdictAction,
     },
-    Moderator, UserManagement,
+    Moderator, UserManagement, MODERATOR_PROMPT_FILE,
 };
-use futures::future::BoxFuture;
-use futures::FutureExt;
 use log::debug;
 use mobot::{
     api::{
-        ChatAction, ChatPermissions, GetChatAdministratorsRequest, GetChatRequest,
-        RestrictChatMemberRequest, SendChatActionRequest, SendMessageRequest,
+        BotCommand, ChatAction, ChatPermissions, GetChatAdministratorsRequest, GetChatRequest,
+        RestrictChatMemberRequest, SendChatActionRequest, SendMessageRequest, SetMyCommandsRequest,
     },
     Action, BotState, Client, Event, State, API,
 };
 use regex::Regex;
 use schemars::schema_for;
-use serde_json::Value;
-use tokio::sync::{mpsc, oneshot};
-use tokio::sync::{RwLockReadGuard, RwLockWriteGuard};
-
-struct ProcessQueue {
-    sender: mpsc::Sender<BoxFuture<'static, ()>>,
-}
-
-impl ProcessQueue {
-    fn new(size: usize) -> ProcessQueue {
-        let (sender, mut receiver) = mpsc::channel(size);
-
-        tokio::spawn(async move {
-            loop {
-                match receiver.recv().await {
-                    Some(f) => f.await,
-                    None => {
-                        log::error!("Task queue is closed.")
-                    }
-                }
-            }
-        });
-
-        ProcessQueue { sender }
-    }
-
-    async fn enqueue<T: Send + 'static>(&self, f: impl Future<Output = T> + Send + 'static) -> T {
-        let (sender, receiver) = oneshot::channel::<T>();
-
-        let _ = self
-            .sender
-            .send(
-                async move {
-                    let v = f.await;
-                    let _ = sender.send(v);
-                }
-                .boxed(),
-            )
-            .await;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio::sync::RwLock;
 
-        receiver.await.unwrap()
-    }
-}
-
-static TASK_QUEUE: OnceLock<Arc<ProcessQueue>> = OnceLock::new();
+const MODLOG_MAX_ENTRIES: usize = 20;
+const MAX_IMPORT_FILE_SIZE: u64 = 5 * 1024 * 1024;
+// Leaves room for the decision in a single Telegram message
+const WHY_MAX_REASONING_LENGTH: usize = 3500;
 
 #[derive(Clone, BotState, Default)]
 pub struct BotController {
     moderator: Moderator,
     user_management: UserManagement,
+    audit_repository: AuditRepository,
+    decision_repository: DecisionRepository,
+    degraded_filter: DegradedModeFilter,
+    work_queue: WorkQueue,
     name: String,
     bot_username: String,
 }
@@ -109,21 +76,53 @@ impl BotController {
             MESSAGE_REACTION_DESCRIPTION.to_string(),
             schema_for!(tools::MessageReactionParam),
         );
-        let _ = TASK_QUEUE.set(Arc::new(ProcessQueue::new(2)));
+        let user_management = UserManagement::new();
+        moderator.set_user_management(user_management.clone());
+        for chat_id in user_management.get_managed_chat_ids() {
+            if let Some(persona) = user_management.get_persona(&chat_id) {
+                moderator.set_chat_persona(&chat_id, persona);
+            }
+        }
         Self {
             moderator,
-            user_management: UserManagement::new(),
+            user_management,
+            audit_repository: AuditRepository::try_init(),
+            decision_repository: DecisionRepository::try_init(),
+            degraded_filter: DegradedModeFilter::from_env(),
+            work_queue: WorkQueue::from_env(),
             name: name.into(),
             bot_username: bot_username.into(),
         }
     }
+
+    /// Language of the texts sent to a chat.
+    fn locale(&self, chat_id: &str) -> Locale {
+        self.moderator.chat_locale(chat_id)
+    }
+
+    /// Name of the bot in a chat, chats with a persona may call it differently.
+    fn display_name(&self, chat_id: &str) -> String {
+        self.user_management
+            .get_persona(chat_id)
+            .and_then(|persona| persona.name)
+            .unwrap_or(self.name.clone())
+    }
+}
+
+/// Copy of the controller to work with while waiting for Telegram or the LLM. Members, logs,
+/// work queue, system prompts, conversation histories and pending sanctions are shared with
+/// the controller, so changes made through the copy apply to the controller as well. The
+/// shared parts are locked only for each single access, never while waiting.
+async fn controller_snapshot(state: &State<BotController>) -> BotController {
+    state.get().read().await.clone()
 }
 
+/// Sends a message with a new API client, returns the id of the sent message.
 async fn send_message(
     message_text: String,
     channel_message_thread_id_opt: Option<i64>,
     chat_id: i64,
-) {
+) -> Option<i64> {
     let message_req = if let Some(message_thread_id) = channel_message_thread_id_opt {
         &SendMessageRequest::new(chat_id, message_text).with_message_thread_id(message_thread_id)
     } else {
@@ -132,9 +131,60 @@ async fn send_message(
     let api_key =
         std::env::var("TELEGRAM_TOKEN").expect("TELEGRAM_TOKEN environment variable not set");
     let api = API::new(Client::new(api_key));
-    let status = api.send_message(message_req).await;
-    if status.is_err() {
-        log::error!("Failed to send message: {:?}", status.err());
+    match api.send_message(message_req).await {
+        Ok(message) => Some(message.message_id),
+        Err(e) => {
+            log::error!("Failed to send message: {:?}", e);
+            None
+        }
+    }
+}
+
+fn localized_commands(locale: Locale) -> Vec<BotCommand> {
+    vec![
+        BotCommand {
+            command: "greeting".into(),
+            description: Text::GreetingCommand.get(locale).into(),
+        },
+        BotCommand {
+            command: "summary".into(),
+            description: Text::SummaryCommand.get(locale).into(),
+        },
+    ]
+}
+
+/// Registers the command descriptions with Telegram, in the configured language by default and
+/// in every supported language for users with that client language.
+pub async fn register_bot_commands(api: &API) -> Result<(), anyhow::Error> {
+    api.set_my_commands(&SetMyCommandsRequest {
+        commands: localized_commands(configured_locale()),
+        ..Default::default()
+    })
+    .await?;
+    for locale in Locale::ALL {
+        api.set_my_commands(&SetMyCommandsRequest {
+            commands: localized_commands(locale),
+            language_code: Some(locale.code().to_string()),
+            ..Default::default()
+        })
+        .await?;
+    }
+    Ok(())
+}
+
+/// Registers the command descriptions of a chat which has chosen its own language.
+async fn register_chat_commands(api: &API, chat_id: i64, locale: Locale) {
+    let request = SetMyCommandsRequest {
+        commands: localized_commands(locale),
+        scope: Some(json!({ "type": "chat", "chat_id": chat_id })),
+        ..Default::default()
+    };
+    if let Err(e) = api.set_my_commands(&request).await {
+        log::warn!(
+            "Failed to register the commands of chat {} cause: {}",
+            chat_id,
+            e
+        );
     }
 }
 
@@ -142,23 +192,16 @@ pub async fn inactive_users_action(
     event: Event,
     state: State<BotController>,
 ) -> Result<Action, anyhow::Error> {
-    let username_opt: Option<String> = event.update.from_user()?.clone().username;
-    let bot_controller = state.get().write().await;
+    let user_id: i64 = event.update.from_user()?.id;
+    let bot_controller: BotController = controller_snapshot(&state).await;
     let months: u64 = 6;
     let months_in_secs: u64 = months * 4 * 7 * 24 * 60 * 60;
-    let inactive_users = bot_controller
-        .user_management
-        .get_inactive_users_since(std::time::Duration::from_secs(months_in_secs));
     let chat_id: i64 = event.update.chat_id()?;
 
-    let managed_chat_id: Option<String> = bot_controller
+    if bot_controller
         .user_management
-        .bot_db
-        .bot_memory
-        .managed_chat_id
-        .clone();
-
-    if managed_chat_id.is_some() && managed_chat_id.unwrap() == chat_id.to_string() {
+        .is_managed_chat(&chat_id.to_string())
+    {
         debug!(
             "This Chat {} is managed and this command is not designed for that purpose",
             chat_id
@@ -166,20 +209,31 @@ pub async fn inactive_users_action(
         return Ok(Action::Done);
     }
 
-    if !bot_controller
+    let administrated_chat_ids: Vec<String> = bot_controller
         .user_management
-        .is_administrator(username_opt.unwrap().as_str())
-        || inactive_users.is_empty()
-    {
-        return Ok(Action::Done);
-    }
+        .get_administrated_chat_ids(user_id);
 
     let mut message = String::new();
-    for user in inactive_users {
-        message.push_str(&format!(
-            "User {} is inactive last {} months\n",
-            user.username, months
-        ));
+    for managed_chat_id in administrated_chat_ids {
+        let inactive_users = bot_controller.user_management.get_inactive_users_since(
+            &managed_chat_id,
+            std::time::Duration::from_secs(months_in_secs),
+        );
+        let locale: Locale = bot_controller.locale(&managed_chat_id);
+        for user in inactive_users {
+            message.push_str(&Text::InactiveUser.format(
+                locale,
+                &[
+                    ("user", &user.username),
+                    ("months", &months.to_string()),
+                    ("chat", &managed_chat_id),
+                ],
+            ));
+            message.push('\n');
+        }
+    }
+    if message.is_empty() {
+        return Ok(Action::Done);
     }
     event
         .api
@@ -189,7 +243,6 @@ pub async fn inactive_users_action(
 }
 
 pub async fn init_bot(event: Event, state: State<BotController>) -> Result<Action, anyhow::Error> {
-    let mut bot_controller = state.get().write().await;
     let chat_type: String = event.update.get_message()?.clone().chat.chat_type;
 
     if chat_type == "private" {
@@ -202,37 +255,31 @@ pub async fn init_bot(event: Event, state: State<BotController>) -> Result<Actio
         .api
         .get_chat_administrators(&GetChatAdministratorsRequest::new(chat_id.to_string()))
         .await?;
-    bot_controller
-        .user_management
-        .set_managed_chat_id(Some(chat_id.to_string()));
-    bot_controller.user_management.clear_administrators();
-
-    admin_list.iter().for_each(|admin| {
-        let username_opt: Option<String> = admin.user.username.clone();
-        if let Some(username) = username_opt {
-            if !bot_controller
-                .user_management
-                .is_administrator(username.as_str())
-            {
-                bot_controller
-                    .user_management
-                    .register_administrator(username);
-            }
-        }
-    });
-
     let chat_full_info_list = event
         .api
         .get_chat(&GetChatRequest::new(chat_id.to_string()))
         .await?;
 
+    let bot_controller: BotController = controller_snapshot(&state).await;
+    bot_controller
+        .user_management
+        .register_managed_chat(chat_id);
+    bot_controller.user_management.set_administrators(
+        chat_id,
+        admin_list.iter().map(|admin| admin.user.id).collect(),
+    );
+
     if let Some(active_usernames) = chat_full_info_list.active_usernames {
         active_usernames
             .iter()
             .enumerate()
             .for_each(|(index, username): (usize, &String)| {
-                if !bot_controller.user_management.contains_username(username) {
+                if !bot_controller
+                    .user_management
+                    .contains_username(chat_id, username)
+                {
                     bot_controller.user_management.add_user(
+                        chat_id,
                         index as i64,
                         username,
                         "",
@@ -250,14 +297,14 @@ pub async fn bot_greeting_action(
     event: Event,
     state: State<BotController>,
 ) -> Result<Action, anyhow::Error> {
-    let username_opt: Option<String> = event.update.from_user()?.clone().username;
-    let bot_controller = state.get().write().await;
+    let user_id: i64 = event.update.from_user()?.id;
+    let bot_controller: BotController = controller_snapshot(&state).await;
     let message_thread_id_opt: Option<i64> = event.update.get_message()?.clone().message_thread_id;
     let chat_id: i64 = event.update.chat_id()?;
 
     if !bot_controller
         .user_management
-        .is_administrator(username_opt.unwrap().as_str())
+        .is_administrator(&chat_id.to_string(), user_id)
     {
         return Ok(Action::Done);
     }
@@ -270,18 +317,18 @@ pub async fn bot_greeting_action(
         })
         .await?;
 
-    let response_rs = bot_controller.moderator.introduce_moderator().await;
-    if let Ok(response) = response_rs {
-        let moderator_feedback: ModeratorMessage = serde_json::from_str(&response)?;
-        if let Some(message_thread_id) = message_thread_id_opt {
-            let message_re =
-                &SendMessageRequest::new(event.update.chat_id()?, moderator_feedback.message)
-                    .with_message_thread_id(message_thread_id);
-
-            event.api.send_message(message_re).await?;
-            return Ok(Action::Done);
-        }
-        return Ok(Action::ReplyText(moderator_feedback.message));
+    let chat_id_str: String = chat_id.to_string();
+    let (reply_sender, reply_receiver) = mpsc::unbounded_channel();
+    let (response_rs, _) = tokio::join!(
+        bot_controller
+            .moderator
+            .introduce_moderator(&chat_id_str, Some(reply_sender)),
+        StreamingReply::new(&event.api, chat_id, message_thread_id_opt)
+            .with_locale(bot_controller.locale(&chat_id_str))
+            .forward(reply_receiver)
+    );
+    if let Err(e) = response_rs {
+        log::error!("Failed to introduce the moderator: {}", e);
     }
     Ok(Action::Done)
 }
@@ -296,8 +343,11 @@ pub async fn handle_chat_messages(
     let reply_to_message_opt = event.update.get_message()?.clone().reply_to_message;
     let first_name: String = event.update.from_user()?.clone().first_name;
     let message: Option<String> = event.update.get_message()?.clone().text;
-    let message_thread_id: Option<i64> = event.update.get_message()?.message_thread_id;
-    let mut bot_controller: RwLockWriteGuard<'_, BotController> = state.get().write().await;
+    let message_thread_id: Option<i64> = forum_topic_id(
+        event.update.get_message()?.message_thread_id,
+        &reply_to_message_opt,
+    );
+    let bot_controller: BotController = controller_snapshot(&state).await;
     let chat_id: i64 = event.update.chat_id()?;
     let date_as_unix_time: i64 = event.update.get_message()?.date;
     let message_id: i64 = event.update.get_message()?.message_id;
@@ -306,13 +356,16 @@ pub async fn handle_chat_messages(
         return Ok(Action::Done);
     }
 
-    let managed_chat_id: Option<String> = bot_controller
+    // Users tracked before chats were registered belong to the first group the bot sees
+    let is_group: bool = event.update.get_message()?.chat.chat_type != "private";
+    let is_managed_chat: bool = bot_controller
         .user_management
-        .bot_db
-        .bot_memory
-        .managed_chat_id
-        .clone();
-    if managed_chat_id.is_some() && managed_chat_id.unwrap() != chat_id.to_string() {
+        .is_managed_chat(&chat_id.to_string())
+        || (is_group
+            && bot_controller
+                .user_management
+                .adopt_unassigned_chat(&chat_id.to_string()));
+    if !is_managed_chat {
         debug!(
             "Chat {} is not registered as managed chat, so it's ignored",
             chat_id
@@ -320,21 +373,19 @@ pub async fn handle_chat_messages(
         return Ok(Action::Done);
     }
 
-    let topic: &str = if let Some(reply_to_message) = reply_to_message_opt.as_ref() {
-        reply_to_message
-            .get("forum_topic_created")
-            .unwrap()
-            .get("name")
-            .unwrap()
-            .as_str()
-            .unwrap()
-    } else {
-        &event.update.get_message()?.clone().chat.title.unwrap()
-    };
+    let topic: String = resolve_topic(
+        &bot_controller.user_management,
+        &chat_id.to_string(),
+        event.update.get_message()?.chat.title.as_deref(),
+        message_thread_id,
+        &reply_to_message_opt,
+    );
 
     let username: String = username_opt.unwrap_or(user_id.to_string());
 
     bot_controller.user_management.update_user_activity(
+        &chat_id.to_string(),
+        &topic,
         &username,
         &first_name,
         user_id,
@@ -343,11 +394,12 @@ pub async fn handle_chat_messages(
 
     let role: &str = bot_controller
         .user_management
-        .determine_user_role(username.as_str());
+        .determine_user_role(&chat_id.to_string(), user_id);
 
+    let bot_name: String = bot_controller.display_name(&chat_id.to_string());
     let text_message: &String = &message.unwrap().replace(
         format!("@{}", bot_controller.bot_username).as_str(),
-        &bot_controller.name,
+        &bot_name,
     );
     let incoming_message = UserMessage {
         channel: topic.to_string(),
@@ -356,47 +408,397 @@ pub async fn handle_chat_messages(
         chat_id: chat_id.to_string(),
         user: first_name,
         message: text_message.to_string(),
-        message_id: message_id,
+        message_id,
         date_unix_time: date_as_unix_time.to_string(),
     };
     let input_json_str = serde_json::to_string(&incoming_message)?;
-    let reply_rs = bot_controller
-        .moderator
-        .chat_forum(input_json_str.as_str())
+    let addressed_to_bot: bool = is_addressed_to_bot(
+        &bot_controller,
+        &bot_name,
+        text_message,
+        &reply_to_message_opt,
+    );
+    let moderated_message = ModeratedMessage {
+        chat_id,
+        message_thread_id,
+        message_id,
+        user_id,
+        username,
+        topic: topic.to_string(),
+        text: text_message.to_string(),
+        input_json: input_json_str,
+        addressed_to_bot,
+        unix_time: last_activity_unix_time,
+    };
+    let work_queue: WorkQueue = bot_controller.work_queue;
+
+    let controller: Arc<RwLock<BotController>> = state.get().clone();
+    let api: Arc<API> = event.api.clone();
+    work_queue
+        .enqueue(
+            &application::history_key(&chat_id.to_string(), message_thread_id),
+            last_activity_unix_time,
+            async move {
+                if let Err(e) = moderate_message(controller, api, moderated_message).await {
+                    log::error!("Failed to moderate message {} cause: {}", message_id, e);
+                }
+            },
+        )
         .await;
+    Ok(Action::Done)
+}
 
-    if let Ok(reply_message) = reply_rs {
-        if reply_message.contains(application::NO_ACTION) || reply_message.trim().is_empty() {
-            return Ok(Action::Done);
+/// Thread id of a message in a forum topic. Supergroups without topics set the thread id for
+/// reply threads as well, their messages belong to the history of the chat.
+fn forum_topic_id(
+    message_thread_id: Option<i64>,
+    reply_to_message_opt: &Option<Value>,
+) -> Option<i64> {
+    let reply_to_message: &Value = reply_to_message_opt.as_ref()?;
+    // Messages of a topic which don't reply to another message reply to its creation
+    let is_topic_message: bool = reply_to_message.get("forum_topic_created").is_some()
+        || reply_to_message
+            .get("is_topic_message")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+    message_thread_id.filter(|_| is_topic_message)
+}
+
+/// Name of the forum topic of a message, the general topic is named like the chat.
+fn resolve_topic(
+    user_management: &UserManagement,
+    chat_id: &str,
+    chat_title_opt: Option<&str>,
+    forum_topic_id_opt: Option<i64>,
+    reply_to_message_opt: &Option<Value>,
+) -> String {
+    let chat_title: &str = chat_title_opt.unwrap_or_default();
+    let Some(forum_topic_id) = forum_topic_id_opt else {
+        return chat_title.to_string();
+    };
+    let created_name_opt: Option<&str> = reply_to_message_opt
+        .as_ref()
+        .and_then(|reply_to_message| reply_to_message.get("forum_topic_created"))
+        .and_then(|forum_topic| forum_topic.get("name"))
+        .and_then(Value::as_str);
+    match created_name_opt {
+        Some(name) => {
+            user_management.set_topic_name(chat_id, forum_topic_id, name);
+            name.to_string()
         }
+        None => user_management
+            .get_topic_name(chat_id, forum_topic_id)
+            .unwrap_or_else(|| format!("{} #{}", chat_title, forum_topic_id)),
+    }
+}
 
-        send_message(reply_message, message_thread_id, event.update.chat_id()?).await;
-        return Ok(Action::Done);
+/// A chat message waiting in the work queue for the decision of the moderator.
+struct ModeratedMessage {
+    chat_id: i64,
+    message_thread_id: Option<i64>,
+    message_id: i64,
+    user_id: i64,
+    username: String,
+    topic: String,
+    text: String,
+    input_json: String,
+    addressed_to_bot: bool,
+    unix_time: u64,
+}
+
+/// Decides on a message and carries out the verdict. The controller is only locked to take a
+/// snapshot when the message's turn has come, the history of the moderator is updated once the
+/// model has answered.
+async fn moderate_message(
+    controller: Arc<RwLock<BotController>>,
+    api: Arc<API>,
+    message: ModeratedMessage,
+) -> Result<(), anyhow::Error> {
+    let bot_controller: BotController = controller.read().await.clone();
+    let moderator: &Moderator = &bot_controller.moderator;
+    let chat_id_str: String = message.chat_id.to_string();
+    let (verdict_rs, (streamed_reply, mut reply_message_ids)) = if !moderator.is_llm_available() {
+        (
+            Err(anyhow::anyhow!("No LLM backend is available")),
+            (String::new(), Vec::new()),
+        )
+    } else if message.addressed_to_bot {
+        let (reply_sender, reply_receiver) = mpsc::unbounded_channel();
+        let streaming_reply = StreamingReply::new(&api, message.chat_id, message.message_thread_id)
+            .with_locale(moderator.chat_locale(&chat_id_str))
+            .without_placeholder();
+        tokio::join!(
+            moderator.chat_forum(
+                &chat_id_str,
+                message.message_thread_id,
+                &message.input_json,
+                Some(reply_sender)
+            ),
+            streaming_reply.forward(reply_receiver)
+        )
+    } else {
+        let verdict_rs = moderator
+            .chat_forum(
+                &chat_id_str,
+                message.message_thread_id,
+                &message.input_json,
+                None,
+            )
+            .await;
+        (verdict_rs, (String::new(), Vec::new()))
+    };
+    record_moderation_sanctions(&bot_controller);
+
+    let verdict: ModerationVerdict = match verdict_rs {
+        Ok(verdict) => verdict,
+        Err(e) => {
+            log::warn!(
+                "No verdict on message {}, falling back to degraded mode cause: {}",
+                message.message_id,
+                e
+            );
+            match degraded_verdict(
+                &bot_controller,
+                &chat_id_str,
+                message.user_id,
+                &message.text,
+            ) {
+                Some(verdict) => verdict,
+                None => {
+                    if message.addressed_to_bot && streamed_reply.trim().is_empty() {
+                        let _ = send_message(
+                            Text::LlmUnavailable
+                                .get(bot_controller.locale(&chat_id_str))
+                                .to_string(),
+                            message.message_thread_id,
+                            message.chat_id,
+                        )
+                        .await;
+                    }
+                    return Ok(());
+                }
+            }
+        }
+    };
+    debug!("Verdict on message {}: {:?}", message.message_id, verdict);
+    match verdict.effective_action() {
+        VerdictAction::None => return Ok(()),
+        VerdictAction::Reply => {}
+        VerdictAction::Warn => record_verdict_warning(
+            &bot_controller,
+            &chat_id_str,
+            message.user_id,
+            &message.username,
+            &verdict,
+            &message.text,
+            message.unix_time,
+        ),
+        VerdictAction::Escalate => {
+            escalate_to_administrators(
+                &bot_controller,
+                &chat_id_str,
+                &message.topic,
+                &message.username,
+                &verdict,
+                &message.text,
+            )
+            .await
+        }
+    }
+    // Replies to messages addressed to the bot have been streamed already
+    let reply: &str = verdict.reply.trim();
+    if !reply.is_empty() && reply != streamed_reply.trim() {
+        reply_message_ids.extend(
+            send_message(
+                reply.to_string(),
+                message.message_thread_id,
+                message.chat_id,
+            )
+            .await,
+        );
+    }
+    bot_controller
+        .decision_repository
+        .record(DecisionEntity::new(
+            &chat_id_str,
+            reply_message_ids,
+            Some(message.text.clone()),
+            serde_json::to_value(&verdict)?,
+            verdict.reasoning.clone(),
+        ));
+    Ok(())
+}
+
+/// Verdict of the deterministic filters for a message the LLM couldn't decide on, admins are
+/// not filtered.
+fn degraded_verdict(
+    bot_controller: &BotController,
+    chat_id: &str,
+    user_id: i64,
+    message_text: &str,
+) -> Option<ModerationVerdict> {
+    if bot_controller
+        .user_management
+        .is_administrator(chat_id, user_id)
+    {
+        return None;
+    }
+    let message_count: u64 = bot_controller
+        .user_management
+        .get_user(chat_id, user_id)
+        .map_or(0, |user| user.message_count);
+    bot_controller
+        .degraded_filter
+        .check(message_text, message_count)
+}
+
+/// Records the warning of a verdict in the profile of the author and in the audit log.
+fn record_verdict_warning(
+    bot_controller: &BotController,
+    chat_id: &str,
+    user_id: i64,
+    username: &str,
+    verdict: &ModerationVerdict,
+    message_text: &str,
+    unix_time: u64,
+) {
+    if bot_controller
+        .user_management
+        .is_administrator(chat_id, user_id)
+    {
+        debug!("User {} is admin, can't be warned", username);
+        return;
+    }
+    let warning_count_opt: Option<usize> = bot_controller.user_management.record_sanction(
+        chat_id,
+        user_id,
+        SanctionEntity::new(
+            unix_time,
+            "warning",
+            "LLM",
+            Some(message_text.to_string()),
+            "success",
+        ),
+    );
+    debug!(
+        "User {} has been warned for rule {:?} ({:?} warnings so far)",
+        username, verdict.violated_rule, warning_count_opt
+    );
+    bot_controller
+        .audit_repository
+        .record(AuditEntryEntity::new(
+            chat_id,
+            "LLM",
+            username,
+            "warning",
+            json!({
+                "user_id": user_id,
+                "violated_rule": verdict.violated_rule,
+                "severity": verdict.severity,
+                "confidence": verdict.confidence,
+            }),
+            Some(message_text.to_string()),
+            "success",
+        ));
+}
+
+/// Notifies the administrators of the chat in private messages and records the escalation in
+/// the audit log. Administrators who never started a conversation with the bot can't be reached.
+async fn escalate_to_administrators(
+    bot_controller: &BotController,
+    chat_id: &str,
+    topic: &str,
+    username: &str,
+    verdict: &ModerationVerdict,
+    message_text: &str,
+) {
+    let administrators: Vec<i64> = bot_controller.user_management.get_administrators(chat_id);
+    let notification: String = Text::Escalation.format(
+        bot_controller.locale(chat_id),
+        &[
+            ("chat", topic),
+            ("user", username),
+            ("message", message_text),
+            (
+                "rule",
+                &verdict
+                    .violated_rule
+                    .map_or(String::from("-"), |rule| rule.to_string()),
+            ),
+            ("severity", &format!("{:?}", verdict.severity)),
+        ],
+    );
+    for administrator in &administrators {
+        let _ = send_message(notification.clone(), None, *administrator).await;
+    }
+    bot_controller
+        .audit_repository
+        .record(AuditEntryEntity::new(
+            chat_id,
+            "LLM",
+            username,
+            "escalation",
+            json!({
+                "violated_rule": verdict.violated_rule,
+                "severity": verdict.severity,
+                "confidence": verdict.confidence,
+                "notified_administrators": administrators.len(),
+            }),
+            Some(message_text.to_string()),
+            "success",
+        ));
+}
+
+/// Messages mentioning the bot or replying to one of its messages expect an answer, which is
+/// streamed then.
+fn is_addressed_to_bot(
+    bot_controller: &BotController,
+    bot_name: &str,
+    text_message: &str,
+    reply_to_message_opt: &Option<Value>,
+) -> bool {
+    let replied_username: Option<&str> = reply_to_message_opt
+        .as_ref()
+        .and_then(|reply_to_message| reply_to_message.get("from")?.get("username")?.as_str());
+    replied_username == Some(bot_controller.bot_username.as_str())
+        || text_message
+            .to_lowercase()
+            .contains(&bot_name.to_lowercase())
+}
+
+/// Applies the mutes and kicks the LLM has imposed to the profiles of the affected members.
+fn record_moderation_sanctions(bot_controller: &BotController) {
+    for sanction in bot_controller.moderator.take_imposed_sanctions() {
+        let action = sanction.action;
+        let Some(kind) = tools::sanction_kind_of(&action.action) else {
+            continue;
+        };
+        bot_controller.user_management.record_sanction(
+            &action.chat_id,
+            sanction.user_id,
+            SanctionEntity::new(
+                action.created_unix_time,
+                kind,
+                &action.actor,
+                action.trigger_message,
+                &action.result,
+            ),
+        );
     }
-    Ok(Action::Done)
 }
 
 pub async fn chat_summarize_action(
     event: Event,
     state: State<BotController>,
 ) -> Result<Action, anyhow::Error> {
-    let bot_controller: RwLockReadGuard<'_, BotController> = state.get().read().await;
+    let bot_controller: BotController = controller_snapshot(&state).await;
     let message_thread_id: Option<i64> = event.update.get_message()?.clone().message_thread_id;
-    let reply_to_message_opt = event.update.get_message()?.clone().reply_to_message;
+    let forum_topic_id_opt: Option<i64> = forum_topic_id(
+        message_thread_id,
+        &event.update.get_message()?.reply_to_message,
+    );
     let chat_id: i64 = event.update.chat_id()?;
 
-    let topic = if let Some(reply_to_message) = reply_to_message_opt.as_ref() {
-        reply_to_message
-            .get("forum_topic_created")
-            .unwrap()
-            .get("name")
-            .unwrap()
-            .as_str()
-            .unwrap()
-    } else {
-        &event.update.get_message()?.clone().chat.title.unwrap()
-    };
-
     event
         .api
         .send_chat_action(&SendChatActionRequest {
@@ -406,41 +808,45 @@ pub async fn chat_summarize_action(
         })
         .await?;
 
-    let summarize_message_rs: Result<String, anyhow::Error> =
-        bot_controller.moderator.summarize_chat(topic).await;
-    if let Ok(summary) = summarize_message_rs {
-        if let Some(thread_id) = message_thread_id {
-            let message_re = &SendMessageRequest::new(event.update.chat_id()?, summary)
-                .with_message_thread_id(thread_id);
-            event.api.send_message(message_re).await?;
-            return Ok(Action::Done);
-        }
-        return Ok(Action::ReplyText(summary));
+    let (reply_sender, reply_receiver) = mpsc::unbounded_channel();
+    let chat_id_str: String = chat_id.to_string();
+    let (summarize_message_rs, _) = tokio::join!(
+        bot_controller.moderator.summarize_chat(
+            &chat_id_str,
+            forum_topic_id_opt,
+            Some(reply_sender)
+        ),
+        StreamingReply::new(&event.api, chat_id, message_thread_id)
+            .with_locale(bot_controller.locale(&chat_id_str))
+            .forward(reply_receiver)
+    );
+    if let Err(e) = summarize_message_rs {
+        log::error!("Failed to summarize the chat: {}", e);
     }
     Ok(Action::Done)
 }
 
-fn extract_user_id_chat_attribute(json: &Option<Value>) -> i64 {
-    json.as_ref()
-        .unwrap()
-        .get("from")
-        .unwrap()
-        .get("id")
-        .unwrap()
-        .as_i64()
-        .unwrap()
+fn extract_user_id_chat_attribute(json: &Option<Value>) -> Option<i64> {
+    json.as_ref()?.get("from")?.get("id")?.as_i64()
 }
 
-fn extract_username_chat_attribute(json: &Option<Value>) -> String {
-    json.as_ref()
-        .unwrap()
-        .get("from")
-        .unwrap()
-        .get("username")
-        .unwrap()
+/// Name of the author for replies and the audit log, members without a public username are
+/// named by their first name.
+fn extract_display_name_chat_attribute(json: &Option<Value>) -> String {
+    let from_opt: Option<&Value> = json.as_ref().and_then(|json| json.get("from"));
+    ["username", "first_name"]
+        .iter()
+        .find_map(|field| from_opt?.get(*field)?.as_str())
+        .map(str::to_string)
+        .or_else(|| extract_user_id_chat_attribute(json).map(|user_id| user_id.to_string()))
+        .unwrap_or_default()
+}
+
+fn extract_text_chat_attribute(json: &Option<Value>) -> Option<String> {
+    json.as_ref()?
+        .get("text")?
         .as_str()
-        .unwrap()
-        .to_string()
+        .map(|text| text.to_string())
 }
 
 fn extract_time_seconds(str: String) -> Option<u64> {
@@ -468,6 +874,7 @@ pub async fn mute_user_action(
     event: Event,
     state: State<BotController>,
 ) -> Result<Action, anyhow::Error> {
+    let user_id: i64 = event.update.from_user()?.id;
     let user_opt: Option<String> = event.update.from_user()?.clone().username;
     let reply_to_message_opt = &event.update.get_message()?.clone().reply_to_message;
     let message_thread_id: Option<i64> = event.update.get_message()?.clone().message_thread_id;
@@ -477,39 +884,44 @@ pub async fn mute_user_action(
         return Ok(Action::Done);
     }
 
-    let user_id_be_muted: i64 = extract_user_id_chat_attribute(reply_to_message_opt);
-    let username_be_muted: String = extract_username_chat_attribute(reply_to_message_opt);
+    let Some(user_id_be_muted) = extract_user_id_chat_attribute(reply_to_message_opt) else {
+        debug!("The replied message has no author");
+        return Ok(Action::Done);
+    };
+    let username_be_muted: String = extract_display_name_chat_attribute(reply_to_message_opt);
 
-    let bot_controller: RwLockReadGuard<'_, BotController> = state.get().read().await;
-    let username: String = user_opt.unwrap_or("unknown".to_string());
+    let bot_controller: BotController = controller_snapshot(&state).await;
+    let chat_id: String = event.update.chat_id()?.to_string();
+    let actor: String = audit_actor_of(user_id, user_opt);
     if !bot_controller
         .user_management
-        .is_administrator(username.as_str())
+        .is_administrator(&chat_id, user_id)
     {
-        debug!("User {} don't have admin permission to mute", username);
+        debug!("User {} don't have admin permission to mute", actor);
         return Ok(Action::Done);
     }
 
     if bot_controller
         .user_management
-        .is_administrator(username_be_muted.as_str())
+        .is_administrator(&chat_id, user_id_be_muted)
     {
         debug!("User {} is admin, can't be muted", username_be_muted);
         return Ok(Action::Done);
     }
 
+    let locale: Locale = bot_controller.locale(&chat_id);
     let time_opt: Option<u64> = extract_time_seconds(message.unwrap_or_default());
     if time_opt.is_none() {
         debug!("No valid time parameter found to mute user. Following format is supported: /mute 10m, 2h, 1d");
         if let Some(thread_id) = message_thread_id {
             let message_re = &SendMessageRequest::new(
                 event.update.chat_id()?,
-                "No valid time parameter found to mute user. Following format is supported: /mute 10m, 2h, 1d",
+                Text::InvalidMuteTime.get(locale),
             )
             .with_message_thread_id(thread_id);
             event.api.send_message(message_re).await?;
         }
-        return Ok(Action::ReplyText("No valid time parameter found to mute user. Following format is supported: /mute 10m, 2h, 1d".into()));
+        return Ok(Action::ReplyText(Text::InvalidMuteTime.get(locale).into()));
     }
     let time_secs: u64 = time_opt.unwrap();
 
@@ -538,31 +950,50 @@ pub async fn mute_user_action(
         until_date: Some(mute_time_60s),
     };
 
-    let is_successful_muted = event.api.restrict_chat_member(&restrict_chat_req).await?;
+    let restrict_rs = event.api.restrict_chat_member(&restrict_chat_req).await;
+    bot_controller
+        .audit_repository
+        .record(AuditEntryEntity::new(
+            &chat_id,
+            &actor,
+            &username_be_muted,
+            "mute",
+            json!({ "user_id": user_id_be_muted, "mute_time_secs": time_secs }),
+            extract_text_chat_attribute(reply_to_message_opt),
+            &audit_result_of(&restrict_rs),
+        ));
+    bot_controller.user_management.record_sanction(
+        &chat_id,
+        user_id_be_muted,
+        SanctionEntity::new(
+            event.update.get_message()?.date as u64,
+            "mute",
+            &actor,
+            extract_text_chat_attribute(reply_to_message_opt),
+            &audit_result_of(&restrict_rs),
+        ),
+    );
+    let is_successful_muted = restrict_rs?;
 
     if !is_successful_muted {
-        return Ok(Action::ReplyText("Failed to mute user".into()));
+        return Ok(Action::ReplyText(Text::MuteFailed.get(locale).into()));
     }
 
+    let reply: String = Text::Muted.format(locale, &[("user", &username_be_muted)]);
     if let Some(thread_id) = message_thread_id {
-        let message_re = &SendMessageRequest::new(
-            event.update.chat_id()?,
-            format!("@{} You are muted now!", username_be_muted),
-        )
-        .with_message_thread_id(thread_id);
+        let message_re = &SendMessageRequest::new(event.update.chat_id()?, reply)
+            .with_message_thread_id(thread_id);
         event.api.send_message(message_re).await?;
         return Ok(Action::Done);
     }
-    Ok(Action::ReplyText(format!(
-        "@{} You are muted now!",
-        username_be_muted
-    )))
+    Ok(Action::ReplyText(reply))
 }
 
 pub async fn unmute_user_action(
     event: Event,
     state: State<BotController>,
 ) -> Result<Action, anyhow::Error> {
+    let user_id: i64 = event.update.from_user()?.id;
     let user_opt: Option<String> = event.update.from_user()?.clone().username;
     let message_thread_id: Option<i64> = event.update.get_message()?.clone().message_thread_id;
     let reply_to_message_opt = &event.update.get_message()?.clone().reply_to_message;
@@ -571,20 +1002,24 @@ pub async fn unmute_user_action(
         return Ok(Action::Done);
     }
 
-    let user_id_be_unmuted: i64 = extract_user_id_chat_attribute(reply_to_message_opt);
-    let username_be_unmuted: String = extract_username_chat_attribute(reply_to_message_opt);
+    let Some(user_id_be_unmuted) = extract_user_id_chat_attribute(reply_to_message_opt) else {
+        debug!("The replied message has no author");
+        return Ok(Action::Done);
+    };
+    let username_be_unmuted: String = extract_display_name_chat_attribute(reply_to_message_opt);
 
-    let bot_controller = state.get().write().await;
+    let bot_controller: BotController = controller_snapshot(&state).await;
+    let chat_id: String = event.update.chat_id()?.to_string();
     if !bot_controller
         .user_management
-        .is_administrator(user_opt.unwrap().as_str())
+        .is_administrator(&chat_id, user_id)
     {
         return Ok(Action::Done);
     }
 
     if bot_controller
         .user_management
-        .is_administrator(username_be_unmuted.as_str())
+        .is_administrator(&chat_id, user_id_be_unmuted)
     {
         debug!("User {} is admin, can't unmute", username_be_unmuted);
         return Ok(Action::Done);
@@ -612,15 +1047,34 @@ pub async fn unmute_user_action(
         use_independent_chat_permissions: Some(false),
         until_date: None,
     };
-    let is_successful_unmuted = event.api.restrict_chat_member(&restrict_chat_req).await?;
+    let restrict_rs = event.api.restrict_chat_member(&restrict_chat_req).await;
+    bot_controller
+        .audit_repository
+        .record(AuditEntryEntity::new(
+            &chat_id,
+            &audit_actor_of(user_id, user_opt),
+            &username_be_unmuted,
+            "unmute",
+            json!({ "user_id": user_id_be_unmuted }),
+            extract_text_chat_attribute(reply_to_message_opt),
+            &audit_result_of(&restrict_rs),
+        ));
+    let is_successful_unmuted = restrict_rs?;
     if !is_successful_unmuted {
-        return Ok(Action::ReplyText("Failed to unmute user".into()));
+        return Ok(Action::ReplyText(
+            Text::UnmuteFailed
+                .get(bot_controller.locale(&chat_id))
+                .into(),
+        ));
     }
 
     if let Some(thread_id) = message_thread_id {
         let message_re = &SendMessageRequest::new(
             event.update.chat_id()?,
-            format!("@{} You are unmuted now!", username_be_unmuted),
+            Text::Unmuted.format(
+                bot_controller.locale(&chat_id),
+                &[("user", &username_be_unmuted)],
+            ),
         )
         .with_message_thread_id(thread_id);
         event.api.send_message(message_re).await?;
@@ -629,23 +1083,606 @@ pub async fn unmute_user_action(
     Ok(Action::Done)
 }
 
-pub async fn killswitch_action(
+/// Admins are named by their username in the audit log, by their user id if they have none.
+fn audit_actor_of(user_id: i64, username_opt: Option<String>) -> String {
+    match username_opt {
+        Some(username) => format!("@{}", username),
+        None => user_id.to_string(),
+    }
+}
+
+fn audit_result_of<E: std::fmt::Display>(result: &Result<bool, E>) -> String {
+    match result {
+        Ok(true) => "success".to_string(),
+        Ok(false) => "failed".to_string(),
+        Err(e) => format!("error: {}", e),
+    }
+}
+
+/// Parses the arguments of `/modlog [user] [since] [until]`, durations like `7d`, `12h` or `30m`
+/// are relative to now, every other argument is taken as user filter.
+fn parse_modlog_query(message: &str) -> AuditQuery {
+    let now: u64 = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let mut query = AuditQuery::default();
+    for argument in message.split_whitespace().skip(1) {
+        match extract_time_seconds(format!(" {} ", argument)) {
+            Some(secs) if query.since_unix_time.is_none() => {
+                query.since_unix_time = Some(now.saturating_sub(secs))
+            }
+            Some(secs) => query.until_unix_time = Some(now.saturating_sub(secs)),
+            None => query.user = Some(argument.to_string()),
+        }
+    }
+    query
+}
+
+fn format_audit_entry(entry: &AuditEntryEntity, locale: Locale) -> String {
+    let now: u64 = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let age_mins: u64 = now.saturating_sub(entry.created_unix_time) / 60;
+    let age: String = match age_mins {
+        0..=59 => format!("{}m", age_mins),
+        60..=1439 => format!("{}h", age_mins / 60),
+        _ => format!("{}d", age_mins / (24 * 60)),
+    };
+    let mut line: String = Text::ModlogEntry.format(
+        locale,
+        &[
+            ("chat", &entry.chat_id),
+            ("age", &age),
+            ("actor", &entry.actor),
+            ("action", &entry.action),
+            ("target", &entry.target),
+            ("result", &entry.result),
+        ],
+    );
+    if let Some(trigger_message) = &entry.trigger_message {
+        let trigger: String = trigger_message.chars().take(80).collect();
+        line.push_str(&Text::ModlogTriggerMessage.format(locale, &[("message", &trigger)]));
+    }
+    line
+}
+
+pub async fn modlog_action(
+    event: Event,
+    state: State<BotController>,
+) -> Result<Action, anyhow::Error> {
+    let user_id: i64 = event.update.from_user()?.id;
+    let message: String = event.update.get_message()?.clone().text.unwrap_or_default();
+    let message_thread_id: Option<i64> = event.update.get_message()?.message_thread_id;
+    let bot_controller: BotController = controller_snapshot(&state).await;
+    let chat_id: i64 = event.update.chat_id()?;
+
+    let chat_ids: Vec<String> = if bot_controller
+        .user_management
+        .is_managed_chat(&chat_id.to_string())
+    {
+        if !bot_controller
+            .user_management
+            .is_administrator(&chat_id.to_string(), user_id)
+        {
+            debug!(
+                "User {} don't have admin permission to read the modlog",
+                user_id
+            );
+            return Ok(Action::Done);
+        }
+        vec![chat_id.to_string()]
+    } else {
+        bot_controller
+            .user_management
+            .get_administrated_chat_ids(user_id)
+    };
+    if chat_ids.is_empty() {
+        return Ok(Action::Done);
+    }
+
+    let query = parse_modlog_query(&message);
+    let mut entries: Vec<AuditEntryEntity> = chat_ids
+        .iter()
+        .flat_map(|managed_chat_id| {
+            bot_controller
+                .audit_repository
+                .query(managed_chat_id, &query)
+        })
+        .collect();
+    entries.sort_by_key(|entry| entry.created_unix_time);
+    let skipped: usize = entries.len().saturating_sub(MODLOG_MAX_ENTRIES);
+    let locale: Locale = bot_controller.locale(&chat_id.to_string());
+    let reply: String = if entries.is_empty() {
+        Text::NoModerationActions.get(locale).to_string()
+    } else {
+        entries
+            .iter()
+            .skip(skipped)
+            .map(|entry| format_audit_entry(entry, locale))
+            .collect::<Vec<String>>()
+            .join("\n")
+    };
+
+    if let Some(thread_id) = message_thread_id {
+        let message_re = &SendMessageRequest::new(chat_id, reply).with_message_thread_id(thread_id);
+        event.api.send_message(message_re).await?;
+        return Ok(Action::Done);
+    }
+    Ok(Action::ReplyText(reply))
+}
+
+pub async fn export_action(
+    event: Event,
+    state: State<BotController>,
+) -> Result<Action, anyhow::Error> {
+    let user_id: i64 = event.update.from_user()?.id;
+    let chat_type: String = event.update.get_message()?.clone().chat.chat_type;
+    let message: String = event.update.get_message()?.clone().text.unwrap_or_default();
+    let chat_id: i64 = event.update.chat_id()?;
+    // Private chats have no persona, their texts use the bot-wide language
+    let locale: Locale = configured_locale();
+    if chat_type != "private" {
+        return Ok(Action::ReplyText(Text::PrivateChatOnly.get(locale).into()));
+    }
+
+    let format: ExportFormat = match message.split_whitespace().nth(1) {
+        None => ExportFormat::Json,
+        Some(argument) => match ExportFormat::from_argument(argument) {
+            Some(format) => format,
+            None => {
+                return Ok(Action::ReplyText(
+                    Text::UnsupportedExportFormat.get(locale).into(),
+                ))
+            }
+        },
+    };
+
+    let bot_controller: BotController = controller_snapshot(&state).await;
+    let administrated_chat_ids: Vec<String> = bot_controller
+        .user_management
+        .get_administrated_chat_ids(user_id);
+    if administrated_chat_ids.is_empty() {
+        return Ok(Action::Done);
+    }
+    let export = BotStateExport {
+        bot_memory: bot_controller
+            .user_management
+            .export_managed_chats(&administrated_chat_ids),
+        audit_log: administrated_chat_ids
+            .iter()
+            .flat_map(|managed_chat_id| {
+                bot_controller
+                    .audit_repository
+                    .query(managed_chat_id, &AuditQuery::default())
+            })
+            .collect(),
+    };
+
+    let content = export
+        .encode(format)
+        .map_err(|e| anyhow::anyhow!("Could not encode export cause: {}", e))?;
+    send_document(
+        chat_id,
+        &format!("ferrisbot_export.{}", format.file_extension()),
+        content,
+        &Text::ExportCaption.format(
+            locale,
+            &[("count", &administrated_chat_ids.len().to_string())],
+        ),
+    )
+    .await?;
+    Ok(Action::Done)
+}
+
+pub async fn import_action(
+    event: Event,
+    state: State<BotController>,
+) -> Result<Action, anyhow::Error> {
+    let user_id: i64 = event.update.from_user()?.id;
+    let chat_type: String = event.update.get_message()?.clone().chat.chat_type;
+    let reply_to_message_opt = event.update.get_message()?.clone().reply_to_message;
+    let locale: Locale = configured_locale();
+    if chat_type != "private" {
+        return Ok(Action::ReplyText(Text::PrivateChatOnly.get(locale).into()));
+    }
+    let bot_controller: BotController = controller_snapshot(&state).await;
+    if bot_controller
+        .user_management
+        .get_administrated_chat_ids(user_id)
+        .is_empty()
+    {
+        return Ok(Action::Done);
+    }
+
+    let Some(document) = reply_to_message_opt
+        .as_ref()
+        .and_then(|reply_to_message| reply_to_message.get("document"))
+    else {
+        return Ok(Action::ReplyText(Text::ImportUsage.get(locale).into()));
+    };
+    let file_id: &str = document
+        .get("file_id")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let file_name: &str = document
+        .get("file_name")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let is_too_large: bool = document
+        .get("file_size")
+        .and_then(Value::as_u64)
+        .is_some_and(|file_size| file_size > MAX_IMPORT_FILE_SIZE);
+    if is_too_large {
+        return Ok(Action::ReplyText(Text::ImportTooLarge.get(locale).into()));
+    }
+
+    let Some(content) = download_document(file_id, MAX_IMPORT_FILE_SIZE).await? else {
+        return Ok(Action::ReplyText(Text::ImportTooLarge.get(locale).into()));
+    };
+    let import = match BotStateExport::decode(&content, ExportFormat::detect(file_name, &content)) {
+        Ok(import) => import,
+        Err(e) => {
+            debug!("Rejected import document {} cause: {}", file_name, e);
+            return Ok(Action::ReplyText(
+                Text::ImportInvalid.format(locale, &[("error", &e.to_string())]),
+            ));
+        }
+    };
+
+    let administrated_chat_ids: Vec<String> = bot_controller
+        .user_management
+        .get_administrated_chat_ids(user_id);
+    let mut merged_users: usize = 0;
+    let mut skipped_chat_ids: Vec<String> = Vec::new();
+    let mut rejected_personas: Vec<String> = Vec::new();
+    for (managed_chat_id, mut managed_chat) in import.bot_memory.managed_chats {
+        if administrated_chat_ids.contains(&managed_chat_id) {
+            match application::validate_persona(&managed_chat.persona) {
+                Ok(persona) => managed_chat.persona = persona,
+                Err(e) => {
+                    rejected_personas.push(format!("{} ({})", managed_chat_id, e));
+                    managed_chat.persona = PersonaEntity::default();
+                }
+            }
+            merged_users += bot_controller
+                .user_management
+                .merge_managed_chat(&managed_chat_id, managed_chat);
+            if let Some(persona) = bot_controller.user_management.get_persona(&managed_chat_id) {
+                bot_controller
+                    .moderator
+                    .set_chat_persona(&managed_chat_id, persona);
+            }
+        } else {
+            skipped_chat_ids.push(managed_chat_id);
+        }
+    }
+    bot_controller.user_management.persist();
+    // The audit log is append-only, exported entries are never written back
+    let mut reply: String = Text::Imported.format(locale, &[("users", &merged_users.to_string())]);
+    if !skipped_chat_ids.is_empty() {
+        reply.push_str(
+            &Text::ImportSkippedChats.format(locale, &[("chats", &skipped_chat_ids.join(", "))]),
+        );
+    }
+    if !rejected_personas.is_empty() {
+        reply.push_str(
+            &Text::ImportRejectedPersonas
+                .format(locale, &[("personas", &rejected_personas.join(", "))]),
+        );
+    }
+    Ok(Action::ReplyText(reply))
+}
+
+/// Adds a fact to the semantic memory of the chat, e.g. `/remember The meetup is on Friday`.
+pub async fn remember_action(
     event: Event,
     state: State<BotController>,
 ) -> Result<Action, anyhow::Error> {
+    let user_id: i64 = event.update.from_user()?.id;
     let user_opt: Option<String> = event.update.from_user()?.clone().username;
-    let bot_controller = state.get().write().await;
+    let message_thread_id: Option<i64> = event.update.get_message()?.clone().message_thread_id;
+    let message: String = event.update.get_message()?.clone().text.unwrap_or_default();
+    let chat_id: String = event.update.chat_id()?.to_string();
+    let bot_controller: BotController = controller_snapshot(&state).await;
     if !bot_controller
         .user_management
-        .is_administrator(user_opt.clone().unwrap().as_str())
+        .is_administrator(&chat_id, user_id)
+    {
+        debug!("User {} don't have admin permission to remember", user_id);
+        return Ok(Action::Done);
+    }
+
+    let fact: String = message
+        .split_once(char::is_whitespace)
+        .map(|(_, fact)| fact.trim().to_string())
+        .unwrap_or_default();
+    let locale: Locale = bot_controller.locale(&chat_id);
+    let reply: String = if fact.is_empty() {
+        Text::RememberUsage.get(locale).to_string()
+    } else {
+        let actor: String = audit_actor_of(user_id, user_opt);
+        match bot_controller
+            .moderator
+            .remember_fact(&chat_id, &actor, &fact)
+            .await
+        {
+            Ok(()) => Text::Remembered.get(locale).to_string(),
+            Err(e) => Text::RememberFailed.format(locale, &[("error", &e.to_string())]),
+        }
+    };
+    if let Some(thread_id) = message_thread_id {
+        let message_re = &SendMessageRequest::new(event.update.chat_id()?, reply)
+            .with_message_thread_id(thread_id);
+        event.api.send_message(message_re).await?;
+        return Ok(Action::Done);
+    }
+    Ok(Action::ReplyText(reply))
+}
+
+/// Shows or changes the persona of the bot in a chat, e.g. `/persona language English`.
+/// A setting without value is reset to the bot-wide default.
+pub async fn persona_action(
+    event: Event,
+    state: State<BotController>,
+) -> Result<Action, anyhow::Error> {
+    let user_id: i64 = event.update.from_user()?.id;
+    let message_thread_id: Option<i64> = event.update.get_message()?.clone().message_thread_id;
+    let message: String = event.update.get_message()?.clone().text.unwrap_or_default();
+    let chat_id: String = event.update.chat_id()?.to_string();
+    let bot_controller: BotController = controller_snapshot(&state).await;
+    if !bot_controller
+        .user_management
+        .is_administrator(&chat_id, user_id)
+    {
+        debug!(
+            "User {} don't have admin permission to edit the persona",
+            user_id
+        );
+        return Ok(Action::Done);
+    }
+
+    let mut persona: PersonaEntity = bot_controller
+        .user_management
+        .get_persona(&chat_id)
+        .unwrap_or_default();
+    let setting: &str = message
+        .split_once(char::is_whitespace)
+        .map(|(_, setting)| setting.trim())
+        .unwrap_or_default();
+    let locale: Locale = bot_controller.locale(&chat_id);
+    let reply: String = if setting.is_empty() {
+        format!(
+            "{}\n\n{}",
+            application::describe_persona(&persona),
+            Text::PersonaUsage.format(
+                locale,
+                &[("fields", &application::PERSONA_FIELDS.join("|"))]
+            )
+        )
+    } else {
+        let (field, value) = setting
+            .split_once(char::is_whitespace)
+            .unwrap_or((setting, ""));
+        match application::set_persona_field(&mut persona, field, value) {
+            Ok(()) => {
+                bot_controller
+                    .user_management
+                    .set_persona(&chat_id, persona.clone());
+                bot_controller.user_management.persist();
+                bot_controller.moderator.set_chat_persona(&chat_id, persona);
+                let locale: Locale = bot_controller.locale(&chat_id);
+                if field == "language" {
+                    register_chat_commands(&event.api, event.update.chat_id()?, locale).await;
+                }
+                Text::PersonaUpdated.format(locale, &[("field", field)])
+            }
+            Err(e) => Text::PersonaRejected.format(locale, &[("error", &e.to_string())]),
+        }
+    };
+    if let Some(thread_id) = message_thread_id {
+        let message_re = &SendMessageRequest::new(event.update.chat_id()?, reply)
+            .with_message_thread_id(thread_id);
+        event.api.send_message(message_re).await?;
+        return Ok(Action::Done);
+    }
+    Ok(Action::ReplyText(reply))
+}
+
+/// Reloads the moderator prompt template from `MODERATOR_PROMPT_FILE`. The conversation
+/// histories are kept, an invalid template is rejected and the current prompt stays in use.
+pub async fn reload_prompt_action(
+    event: Event,
+    state: State<BotController>,
+) -> Result<Action, anyhow::Error> {
+    let user_id: i64 = event.update.from_user()?.id;
+    let message_thread_id: Option<i64> = event.update.get_message()?.clone().message_thread_id;
+    let bot_controller: BotController = controller_snapshot(&state).await;
+    if bot_controller
+        .user_management
+        .get_administrated_chat_ids(user_id)
+        .is_empty()
+    {
+        debug!(
+            "User {} don't have admin permission to reload the prompt",
+            user_id
+        );
+        return Ok(Action::Done);
+    }
+
+    let locale: Locale = bot_controller.locale(&event.update.chat_id()?.to_string());
+    let reply: String = match application::read_prompt_template(MODERATOR_PROMPT_FILE) {
+        Ok(template) => {
+            bot_controller.moderator.reload_prompt_template(&template);
+            log::info!("Prompt template reloaded by user {}", user_id);
+            Text::PromptReloaded.get(locale).to_string()
+        }
+        Err(e) => {
+            log::warn!("Rejected prompt template cause: {}", e);
+            Text::PromptRejected.format(locale, &[("error", &e.to_string())])
+        }
+    };
+    if let Some(thread_id) = message_thread_id {
+        let message_re = &SendMessageRequest::new(event.update.chat_id()?, reply)
+            .with_message_thread_id(thread_id);
+        event.api.send_message(message_re).await?;
+        return Ok(Action::Done);
+    }
+    Ok(Action::ReplyText(reply))
+}
+
+/// Decision and reasoning of the model behind a bot message, as shown by /why.
+fn format_decision(decision: &DecisionEntity, locale: Locale) -> String {
+    let verdict_field = |field: &str| match &decision.verdict[field] {
+        Value::String(value) => value.clone(),
+        Value::Null => String::from("-"),
+        value => value.to_string(),
+    };
+    let mut text: String = Text::WhyDecision.format(
+        locale,
+        &[
+            ("action", &verdict_field("action")),
+            ("rule", &verdict_field("violated_rule")),
+            ("severity", &verdict_field("severity")),
+            ("confidence", &verdict_field("confidence")),
+        ],
+    );
+    if let Some(trigger_message) = &decision.trigger_message {
+        text.push_str(&Text::ModlogTriggerMessage.format(locale, &[("message", trigger_message)]));
+    }
+    text.push_str("\n\n");
+    match &decision.reasoning {
+        Some(reasoning) => {
+            let mut shown_reasoning: String =
+                reasoning.chars().take(WHY_MAX_REASONING_LENGTH).collect();
+            if shown_reasoning.len() < reasoning.len() {
+                shown_reasoning.push('…');
+            }
+            text.push_str(&Text::WhyReasoning.format(locale, &[("reasoning", &shown_reasoning)]));
+        }
+        None => text.push_str(Text::WhyNoReasoning.get(locale)),
+    }
+    text
+}
+
+/// Shows admins the decision behind the bot message they reply to with /why.
+pub async fn why_action(
+    event: Event,
+    state: State<BotController>,
+) -> Result<Action, anyhow::Error> {
+    let user_id: i64 = event.update.from_user()?.id;
+    let message_thread_id: Option<i64> = event.update.get_message()?.clone().message_thread_id;
+    let reply_to_message_opt = event.update.get_message()?.clone().reply_to_message;
+    let chat_id: String = event.update.chat_id()?.to_string();
+    let bot_controller: BotController = controller_snapshot(&state).await;
+    if !bot_controller
+        .user_management
+        .is_administrator(&chat_id, user_id)
+    {
+        debug!(
+            "User {} don't have admin permission to inspect decisions",
+            user_id
+        );
+        return Ok(Action::Done);
+    }
+
+    let locale: Locale = bot_controller.locale(&chat_id);
+    let replied_message_id_opt: Option<i64> = reply_to_message_opt
+        .as_ref()
+        .and_then(|reply_to_message| reply_to_message.get("message_id")?.as_i64());
+    let reply: String = match replied_message_id_opt {
+        None => Text::WhyUsage.get(locale).to_string(),
+        Some(replied_message_id) => match bot_controller
+            .decision_repository
+            .find_by_message(&chat_id, replied_message_id)
+        {
+            Some(decision) => format_decision(&decision, locale),
+            None => Text::WhyUnknown.get(locale).to_string(),
+        },
+    };
+    if let Some(thread_id) = message_thread_id {
+        let message_re = &SendMessageRequest::new(event.update.chat_id()?, reply)
+            .with_message_thread_id(thread_id);
+        event.api.send_message(message_re).await?;
+        return Ok(Action::Done);
+    }
+    Ok(Action::ReplyText(reply))
+}
+
+pub async fn killswitch_action(
+    event: Event,
+    state: State<BotController>,
+) -> Result<Action, anyhow::Error> {
+    let user_id: i64 = event.update.from_user()?.id;
+    let bot_controller: BotController = controller_snapshot(&state).await;
+    if bot_controller
+        .user_management
+        .get_administrated_chat_ids(user_id)
+        .is_empty()
     {
         log::info!(
             "User {} is not an administrator. Ignoring killswitch command.",
-            user_opt.unwrap()
+            user_id
         );
         return Ok(Action::Done);
     }
 
     log::info!("Killswitch command received. Killing the server...");
+    flush_pending_writes().await;
     std::process::exit(0);
 }
+
+#[cfg(test)]
+mod endpoints_test {
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use crate::adapter::endpoints::{forum_topic_id, resolve_topic};
+    use crate::adapter::{
+        test_directory::TestDirectory, BotDatabase, BotMemoryEntity, JsonFileStorage,
+    };
+    use crate::UserManagement;
+
+    const CHAT_ID: &str = "-100";
+
+    #[test]
+    fn should_resolve_topic_of_reply_inside_forum_topic() {
+        let directory = TestDirectory::new("topics");
+        let mut bot_memory = BotMemoryEntity::new();
+        bot_memory
+            .managed_chats
+            .insert(CHAT_ID.to_string(), Default::default());
+        let user_management = UserManagement::with_database(BotDatabase::with_storage(
+            Arc::new(JsonFileStorage::new(&directory.database_file())),
+            bot_memory,
+        ));
+        let resolve = |message_thread_id: Option<i64>, reply_to_message: serde_json::Value| {
+            let reply_to_message_opt = Some(reply_to_message);
+            let topic_id_opt = forum_topic_id(message_thread_id, &reply_to_message_opt);
+            let topic = resolve_topic(
+                &user_management,
+                CHAT_ID,
+                Some("Rustaceans"),
+                topic_id_opt,
+                &reply_to_message_opt,
+            );
+            (topic_id_opt, topic)
+        };
+
+        let first_message = resolve(
+            Some(7),
+            json!({ "message_id": 7, "forum_topic_created": { "name": "Embedded" } }),
+        );
+        let reply_in_topic = resolve(
+            Some(7),
+            json!({ "message_id": 9, "message_thread_id": 7, "is_topic_message": true }),
+        );
+        let reply_thread = resolve(Some(12), json!({ "message_id": 12, "text": "Hello" }));
+
+        assert_eq!(first_message, (Some(7), "Embedded".to_string()));
+        assert_eq!(reply_in_topic, (Some(7), "Embedded".to_string()));
+        assert_eq!(reply_thread, (None, "Rustaceans".to_string()));
+    }
+}
@@ -0,0 +1,92 @@
+use std::{env, sync::Arc, time::SystemTime};
+
+use log::{debug, error};
+
+use crate::adapter::{
+    open_configured_storage, BotStorage, HistoryEntryEntity, JsonFileStorage, WriteBehindFlusher,
+};
+
+const HISTORY_RETENTION_ENV: &str = "HISTORY_RETENTION_SECS";
+const DEFAULT_HISTORY_RETENTION_SECS: u64 = 24 * 60 * 60;
+
+/// Snapshots of the moderator conversation history, so a restart doesn't wipe the context.
+/// Entries older than the configured retention are neither stored nor restored,
+/// a retention of 0 disables the persistence of the history.
+#[derive(Clone)]
+pub struct HistoryRepository {
+    storage: Arc<dyn BotStorage>,
+    retention_secs: u64,
+}
+
+impl Default for HistoryRepository {
+    fn default() -> Self {
+        Self {
+            storage: Arc::new(JsonFileStorage::from_env()),
+            retention_secs: 0,
+        }
+    }
+}
+
+impl HistoryRepository {
+    pub fn try_init() -> Self {
+        let retention_secs = env::var(HISTORY_RETENTION_ENV)
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(DEFAULT_HISTORY_RETENTION_SECS);
+        Self {
+            storage: open_configured_storage(),
+            retention_secs,
+        }
+    }
+
+    fn is_retained(&self, entry: &HistoryEntryEntity) -> bool {
+        let now: u64 = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        now.saturating_sub(entry.created_unix_time) <= self.retention_secs
+    }
+
+    pub fn load(&self, key: &str) -> Vec<HistoryEntryEntity> {
+        if self.retention_secs == 0 {
+            return Vec::new();
+        }
+        let history_rs =
+            match WriteBehindFlusher::running().and_then(|flusher| flusher.pending_history(key)) {
+                Some(entries) => Ok(entries),
+                None => self.storage.load_history(key),
+            };
+        match history_rs {
+            Ok(entries) => {
+                let retained: Vec<HistoryEntryEntity> = entries
+                    .into_iter()
+                    .filter(|entry| self.is_retained(entry))
+                    .collect();
+                debug!("Restored {} history entries for {}", retained.len(), key);
+                retained
+            }
+            Err(e) => {
+                error!("Could not restore history {} cause: {}", key, e);
+                Vec::new()
+            }
+        }
+    }
+
+    pub fn save<'a>(&self, key: &str, entries: impl Iterator<Item = &'a HistoryEntryEntity>) {
+        if self.retention_secs == 0 {
+            return;
+        }
+        let retained: Vec<HistoryEntryEntity> = entries
+            .filter(|entry| self.is_retained(entry))
+            .cloned()
+            .collect();
+        match WriteBehindFlusher::running() {
+            Some(flusher) => flusher.save_history(key, retained),
+            None => {
+                if let Err(e) = self.storage.save_history(key, &retained) {
+                    error!("Could not save history {} cause: {}", key, e);
+                }
+            }
+        }
+    }
+}
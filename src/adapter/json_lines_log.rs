@@ -0,0 +1,234 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+    error::Error,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    sync::{mpsc, Arc, Mutex, OnceLock},
+    thread,
+};
+
+use log::{error, warn};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::adapter::{AuditEntryEntity, DecisionEntity, MemoryEntryEntity};
+
+static LOGS: OnceLock<Mutex<HashMap<String, OpenLog>>> = OnceLock::new();
+
+/// Entry of an append-only log, looked up by the chat it belongs to.
+pub trait ChatLogEntry: Serialize + DeserializeOwned + Clone + Send + Sync + 'static {
+    fn chat_id(&self) -> &str;
+}
+
+impl ChatLogEntry for AuditEntryEntity {
+    fn chat_id(&self) -> &str {
+        &self.chat_id
+    }
+}
+
+impl ChatLogEntry for MemoryEntryEntity {
+    fn chat_id(&self) -> &str {
+        &self.chat_id
+    }
+}
+
+impl ChatLogEntry for DecisionEntity {
+    fn chat_id(&self) -> &str {
+        &self.chat_id
+    }
+}
+
+enum LogWrite {
+    Line(String),
+    Flush(mpsc::Sender<()>),
+}
+
+struct OpenLog {
+    log: Arc<dyn Any + Send + Sync>,
+    writer: mpsc::Sender<LogWrite>,
+}
+
+/// Append-only JSON lines file which is read once and then kept in memory by chat, so lookups
+/// never scan the file. Appends are written and synced in order by a background thread, the
+/// caller never waits for disk I/O. Every storage appending to the same file shares one log.
+pub struct JsonLinesLog<T> {
+    entries_by_chat: Mutex<HashMap<String, Vec<T>>>,
+    writer: mpsc::Sender<LogWrite>,
+}
+
+impl<T: ChatLogEntry> JsonLinesLog<T> {
+    pub fn open(file_path: &str) -> Result<Arc<Self>, Box<dyn Error>> {
+        let mut logs = LOGS.get_or_init(Default::default).lock().unwrap();
+        if let Some(open_log) = logs.get(file_path) {
+            return open_log
+                .log
+                .clone()
+                .downcast::<Self>()
+                .map_err(|_| format!("{} is opened with another entry type", file_path).into());
+        }
+
+        let mut entries_by_chat: HashMap<String, Vec<T>> = HashMap::new();
+        for entry in read_json_lines::<T>(file_path)? {
+            entries_by_chat
+                .entry(entry.chat_id().to_string())
+                .or_default()
+                .push(entry);
+        }
+        let (writer, receiver) = mpsc::channel();
+        let thread_file_path = file_path.to_string();
+        thread::Builder::new()
+            .name("json-lines-writer".to_string())
+            .spawn(move || write_lines(&thread_file_path, receiver))?;
+        let log = Arc::new(Self {
+            entries_by_chat: Mutex::new(entries_by_chat),
+            writer: writer.clone(),
+        });
+        logs.insert(
+            file_path.to_string(),
+            OpenLog {
+                log: log.clone(),
+                writer,
+            },
+        );
+        Ok(log)
+    }
+
+    pub fn append(&self, entry: &T) -> Result<(), Box<dyn Error>> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let mut entries_by_chat = self.entries_by_chat.lock().unwrap();
+        self.writer.send(LogWrite::Line(line))?;
+        entries_by_chat
+            .entry(entry.chat_id().to_string())
+            .or_default()
+            .push(entry.clone());
+        Ok(())
+    }
+
+    /// Entries of a chat, oldest first.
+    pub fn entries(&self, chat_id: &str) -> Vec<T> {
+        self.entries_by_chat
+            .lock()
+            .unwrap()
+            .get(chat_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// Blocks until every line appended so far is written to disk.
+pub fn flush_json_logs() {
+    let Some(logs) = LOGS.get() else {
+        return;
+    };
+    let writers: Vec<mpsc::Sender<LogWrite>> = logs
+        .lock()
+        .unwrap()
+        .values()
+        .map(|open_log| open_log.writer.clone())
+        .collect();
+    for writer in writers {
+        let (done, flushed) = mpsc::channel();
+        if writer.send(LogWrite::Flush(done)).is_ok() {
+            let _ = flushed.recv();
+        }
+    }
+}
+
+fn write_lines(file_path: &str, receiver: mpsc::Receiver<LogWrite>) {
+    while let Ok(write) = receiver.recv() {
+        // Lines which queued up meanwhile are written with a single sync
+        let mut lines = String::new();
+        let mut flushes = Vec::new();
+        let mut next = Some(write);
+        while let Some(write) = next {
+            match write {
+                LogWrite::Line(line) => lines.push_str(&line),
+                LogWrite::Flush(done) => flushes.push(done),
+            }
+            next = receiver.try_recv().ok();
+        }
+        if !lines.is_empty() {
+            if let Err(e) = append_lines(file_path, &lines) {
+                error!("Could not append to {} cause: {}", file_path, e);
+            }
+        }
+        for done in flushes {
+            let _ = done.send(());
+        }
+    }
+}
+
+fn append_lines(file_path: &str, lines: &str) -> Result<(), Box<dyn Error>> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file_path)?;
+    file.write_all(lines.as_bytes())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+fn read_json_lines<T: DeserializeOwned>(file_path: &str) -> Result<Vec<T>, Box<dyn Error>> {
+    if !Path::new(file_path).exists() {
+        return Ok(Vec::new());
+    }
+    let reader = BufReader::new(File::open(file_path)?);
+    let mut values = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        // A crash while appending can leave a truncated last line behind
+        match serde_json::from_str::<T>(&line) {
+            Ok(value) => values.push(value),
+            Err(e) => warn!("Skipping unreadable line of {} cause: {}", file_path, e),
+        }
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod json_lines_log_test {
+    use std::fs;
+
+    use serde_json::json;
+
+    use crate::adapter::{
+        flush_json_logs, test_directory::TestDirectory, AuditEntryEntity, BotStorage,
+        JsonFileStorage, JsonLinesLog,
+    };
+
+    #[test]
+    fn should_share_appended_entries_and_write_them_on_flush() {
+        let directory = TestDirectory::new("json_lines");
+        let file_path = directory.database_file();
+        let writing_storage = JsonFileStorage::new(&file_path);
+        let reading_storage = JsonFileStorage::new(&file_path);
+
+        for chat_id in ["-100", "-200", "-100"] {
+            writing_storage
+                .append_audit(&AuditEntryEntity::new(
+                    chat_id,
+                    "LLM",
+                    "kevin",
+                    "mute",
+                    json!({}),
+                    None,
+                    "success",
+                ))
+                .unwrap();
+        }
+        flush_json_logs();
+
+        assert_eq!(reading_storage.load_audit("-100").unwrap().len(), 2);
+        assert_eq!(reading_storage.load_audit("-300").unwrap().len(), 0);
+        let audit_file_path = directory.path().join("bot_audit.jsonl");
+        let written = fs::read_to_string(&audit_file_path).unwrap();
+        assert_eq!(written.lines().count(), 3);
+        let log = JsonLinesLog::<AuditEntryEntity>::open(&audit_file_path.to_string_lossy());
+        assert_eq!(log.unwrap().entries("-200").len(), 1);
+    }
+}
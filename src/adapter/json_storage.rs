@@ -0,0 +1,407 @@
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use log::{debug, warn};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::adapter::{
+    migrate, AuditEntryEntity, BotMemoryEntity, BotStorage, DecisionEntity, HistoryEntryEntity,
+    JsonLinesLog, MemoryEntryEntity, UnsupportedSchemaVersion,
+};
+
+const FILE_NAME: &str = "bot_db.json";
+const HISTORY_DIRECTORY_NAME: &str = "bot_history";
+const LEGACY_HISTORY_FILE_NAME: &str = "bot_history.json";
+const AUDIT_FILE_NAME: &str = "bot_audit.jsonl";
+const SEMANTIC_MEMORY_FILE_NAME: &str = "bot_semantic_memory.jsonl";
+const DECISION_FILE_NAME: &str = "bot_decisions.jsonl";
+pub const FILE_DB_PATH_ENV: &str = "FILE_DB_PATH";
+const BACKUP_COUNT_ENV: &str = "DB_BACKUP_COUNT";
+const BACKUP_INTERVAL_ENV: &str = "DB_BACKUP_INTERVAL_SECS";
+const DEFAULT_BACKUP_COUNT: usize = 5;
+const DEFAULT_BACKUP_INTERVAL_SECS: u64 = 60 * 60;
+const BACKUP_EXTENSION: &str = "bak";
+
+/// Stores the whole bot memory as a single JSON file.
+///
+/// Writes go to a temporary file which is synced and renamed over the database file,
+/// so a crash never leaves a half written database behind. Before a readable database file
+/// is replaced, a timestamped backup is taken at most once per backup interval and only the
+/// newest `backup_count` backups are kept. Conversation history snapshots live in one file per
+/// history in the `bot_history` directory next to the database file, the moderation audit log,
+/// the semantic memory and the decision log are appended line by line to `bot_audit.jsonl`,
+/// `bot_semantic_memory.jsonl` and `bot_decisions.jsonl`, see [`JsonLinesLog`].
+pub struct JsonFileStorage {
+    file_path: String,
+    history_directory: PathBuf,
+    legacy_history_file_path: PathBuf,
+    audit_file_path: String,
+    semantic_memory_file_path: String,
+    decision_file_path: String,
+    backup_count: usize,
+    backup_interval_secs: u64,
+}
+
+impl JsonFileStorage {
+    pub fn new(file_path: &str) -> Self {
+        Self {
+            file_path: file_path.to_string(),
+            history_directory: Path::new(file_path).with_file_name(HISTORY_DIRECTORY_NAME),
+            legacy_history_file_path: Path::new(file_path).with_file_name(LEGACY_HISTORY_FILE_NAME),
+            audit_file_path: Path::new(file_path)
+                .with_file_name(AUDIT_FILE_NAME)
+                .to_string_lossy()
+                .to_string(),
+            semantic_memory_file_path: Path::new(file_path)
+                .with_file_name(SEMANTIC_MEMORY_FILE_NAME)
+                .to_string_lossy()
+                .to_string(),
+            decision_file_path: Path::new(file_path)
+                .with_file_name(DECISION_FILE_NAME)
+                .to_string_lossy()
+                .to_string(),
+            backup_count: env::var(BACKUP_COUNT_ENV)
+                .ok()
+                .and_then(|count| count.parse().ok())
+                .unwrap_or(DEFAULT_BACKUP_COUNT),
+            backup_interval_secs: env::var(BACKUP_INTERVAL_ENV)
+                .ok()
+                .and_then(|secs| secs.parse().ok())
+                .unwrap_or(DEFAULT_BACKUP_INTERVAL_SECS),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let file_path = env::var(FILE_DB_PATH_ENV).unwrap_or_else(|_| "./".to_string());
+        Self::new(&format!("{}{}", file_path, FILE_NAME))
+    }
+
+    fn read_file(path: &Path) -> Result<BotMemoryEntity, Box<dyn Error>> {
+        let file = File::open(path).map_err(|e| format!("Could not open file cause: {}", e))?;
+        let reader = BufReader::new(file);
+        let document: Value = serde_json::from_reader(reader)
+            .map_err(|e| format!("Could not parse file cause: {}", e))?;
+        let bot_memory: BotMemoryEntity = serde_json::from_value(migrate(document)?)
+            .map_err(|e| format!("Could not parse file cause: {}", e))?;
+        Ok(bot_memory)
+    }
+
+    /// File of the history stored under `key`, `:` is not allowed in file names everywhere.
+    fn history_file_path(&self, key: &str) -> PathBuf {
+        self.history_directory
+            .join(format!("{}.json", key.replace(':', "_")))
+    }
+
+    fn read_history_file(path: &Path) -> Result<Vec<HistoryEntryEntity>, Box<dyn Error>> {
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Histories saved before they got a file each are only read, until they are saved again.
+    fn read_legacy_history(&self, key: &str) -> Result<Vec<HistoryEntryEntity>, Box<dyn Error>> {
+        if !self.legacy_history_file_path.exists() {
+            return Ok(Vec::new());
+        }
+        let file = File::open(&self.legacy_history_file_path)?;
+        let reader = BufReader::new(file);
+        let mut history: HashMap<String, Vec<HistoryEntryEntity>> =
+            serde_json::from_reader(reader)?;
+        Ok(history.remove(key).unwrap_or_default())
+    }
+
+    /// Returns all backups of the database file, newest first.
+    fn list_backups(&self) -> Vec<(u64, PathBuf)> {
+        let path = Path::new(&self.file_path);
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let prefix = format!("{}.", file_name);
+        let suffix = format!(".{}", BACKUP_EXTENSION);
+
+        let mut backups: Vec<(u64, PathBuf)> = fs::read_dir(directory)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .filter_map(|entry| {
+                        let name = entry.file_name().to_string_lossy().to_string();
+                        let timestamp: u64 = name
+                            .strip_prefix(&prefix)?
+                            .strip_suffix(&suffix)?
+                            .parse()
+                            .ok()?;
+                        Some((timestamp, entry.path()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        backups.sort_by_key(|(timestamp, _)| std::cmp::Reverse(*timestamp));
+        backups
+    }
+
+    fn rotate_backups(&self) -> Result<(), Box<dyn Error>> {
+        if self.backup_count == 0 || !Path::new(&self.file_path).exists() {
+            return Ok(());
+        }
+        let now: u64 = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        let backups = self.list_backups();
+        if let Some((newest, _)) = backups.first() {
+            if now.saturating_sub(*newest) < self.backup_interval_secs {
+                return Ok(());
+            }
+        }
+
+        // A corrupt database file must never push out a readable backup
+        Self::read_file(Path::new(&self.file_path))
+            .map_err(|e| format!("Skipping backup of unreadable database file cause: {}", e))?;
+        let backup_path = format!("{}.{}.{}", self.file_path, now, BACKUP_EXTENSION);
+        fs::copy(&self.file_path, &backup_path)?;
+        debug!("Created database backup {}", backup_path);
+
+        for (_, outdated_backup) in backups.iter().skip(self.backup_count - 1) {
+            if let Err(e) = fs::remove_file(outdated_backup) {
+                warn!(
+                    "Could not remove outdated backup {} cause: {}",
+                    outdated_backup.display(),
+                    e
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn recover_from_backups(&self) -> Option<BotMemoryEntity> {
+        self.list_backups()
+            .into_iter()
+            .find_map(|(_, backup_path)| match Self::read_file(&backup_path) {
+                Ok(bot_memory) => {
+                    warn!("Recovered bot memory from backup {}", backup_path.display());
+                    Some(bot_memory)
+                }
+                Err(e) => {
+                    warn!("Skipping backup {} cause: {}", backup_path.display(), e);
+                    None
+                }
+            })
+    }
+}
+
+/// Serializes `value` as JSON into `file_path` without ever exposing a partially written file:
+/// the content is written to a temporary file, synced to disk and renamed over the target.
+pub fn write_json_atomically<T: Serialize>(
+    file_path: &str,
+    value: &T,
+) -> Result<(), Box<dyn Error>> {
+    let tmp_file_path = format!("{}.tmp", file_path);
+    let file = File::create(&tmp_file_path)?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer(&mut writer, value)?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    drop(writer);
+
+    fs::rename(&tmp_file_path, file_path)?;
+    if let Some(parent) = Path::new(file_path).parent() {
+        if !parent.as_os_str().is_empty() {
+            // Persist the rename itself, not supported on every platform
+            if let Ok(directory) = File::open(parent) {
+                let _ = directory.sync_all();
+            }
+        }
+    }
+    Ok(())
+}
+
+impl BotStorage for JsonFileStorage {
+    fn load(&self) -> Result<Option<BotMemoryEntity>, Box<dyn Error>> {
+        if !Path::new(&self.file_path).exists() {
+            return Ok(self.recover_from_backups());
+        }
+        match Self::read_file(Path::new(&self.file_path)) {
+            Ok(bot_memory) => Ok(Some(bot_memory)),
+            Err(e) if e.is::<UnsupportedSchemaVersion>() => Err(e),
+            Err(e) => {
+                warn!("Database file {} is not readable: {}", self.file_path, e);
+                self.recover_from_backups().map(Some).ok_or(e)
+            }
+        }
+    }
+
+    fn save(&self, bot_memory: &BotMemoryEntity) -> Result<(), Box<dyn Error>> {
+        if let Err(e) = self.rotate_backups() {
+            warn!("Could not create database backup cause: {}", e);
+        }
+        write_json_atomically(&self.file_path, bot_memory)
+    }
+
+    fn load_history(&self, key: &str) -> Result<Vec<HistoryEntryEntity>, Box<dyn Error>> {
+        let history_file_path: PathBuf = self.history_file_path(key);
+        if !history_file_path.exists() {
+            return self.read_legacy_history(key);
+        }
+        Self::read_history_file(&history_file_path)
+    }
+
+    fn save_history(
+        &self,
+        key: &str,
+        entries: &[HistoryEntryEntity],
+    ) -> Result<(), Box<dyn Error>> {
+        let history_file_path: PathBuf = self.history_file_path(key);
+        if history_file_path.exists() {
+            Self::read_history_file(&history_file_path).map_err(|e| {
+                format!(
+                    "Refusing to overwrite unreadable history file {} cause: {}",
+                    history_file_path.display(),
+                    e
+                )
+            })?;
+        }
+        fs::create_dir_all(&self.history_directory)?;
+        write_json_atomically(&history_file_path.to_string_lossy(), &entries)
+    }
+
+    fn append_audit(&self, entry: &AuditEntryEntity) -> Result<(), Box<dyn Error>> {
+        JsonLinesLog::open(&self.audit_file_path)?.append(entry)
+    }
+
+    fn load_audit(&self, chat_id: &str) -> Result<Vec<AuditEntryEntity>, Box<dyn Error>> {
+        Ok(JsonLinesLog::<AuditEntryEntity>::open(&self.audit_file_path)?.entries(chat_id))
+    }
+
+    fn append_memory(&self, entry: &MemoryEntryEntity) -> Result<(), Box<dyn Error>> {
+        JsonLinesLog::open(&self.semantic_memory_file_path)?.append(entry)
+    }
+
+    fn load_memory(&self, chat_id: &str) -> Result<Vec<MemoryEntryEntity>, Box<dyn Error>> {
+        Ok(
+            JsonLinesLog::<MemoryEntryEntity>::open(&self.semantic_memory_file_path)?
+                .entries(chat_id),
+        )
+    }
+
+    fn append_decision(&self, entry: &DecisionEntity) -> Result<(), Box<dyn Error>> {
+        JsonLinesLog::open(&self.decision_file_path)?.append(entry)
+    }
+
+    fn load_decisions(&self, chat_id: &str) -> Result<Vec<DecisionEntity>, Box<dyn Error>> {
+        Ok(JsonLinesLog::<DecisionEntity>::open(&self.decision_file_path)?.entries(chat_id))
+    }
+}
+
+#[cfg(test)]
+mod json_storage_test {
+    use std::fs;
+
+    use ollama_rs::generation::chat::ChatMessage;
+
+    use crate::adapter::{
+        test_directory::TestDirectory, BotMemoryEntity, BotStorage, HistoryEntryEntity,
+        JsonFileStorage, UserEntity,
+    };
+
+    const CHAT_ID: &str = "-100";
+
+    fn bot_memory_with_user(user_id: i64, username: &str) -> BotMemoryEntity {
+        let mut bot_memory = BotMemoryEntity::new();
+        bot_memory
+            .managed_chats
+            .entry(CHAT_ID.to_string())
+            .or_default()
+            .user_map
+            .insert(
+                user_id.to_string(),
+                UserEntity::new(user_id, username, username, 1),
+            );
+        bot_memory
+    }
+
+    #[test]
+    fn should_load_saved_memory_successfully() {
+        let directory = TestDirectory::new("roundtrip");
+        let file_path = directory.database_file();
+        let storage = JsonFileStorage::new(&file_path);
+
+        storage.save(&bot_memory_with_user(1, "sabine")).unwrap();
+        let loaded = storage.load().unwrap().unwrap();
+
+        let user_map = &loaded.managed_chats.get(CHAT_ID).unwrap().user_map;
+        assert_eq!(user_map.get("1").unwrap().username, "sabine");
+        assert!(!std::path::Path::new(&format!("{}.tmp", file_path)).exists());
+    }
+
+    #[test]
+    fn should_recover_from_backup_on_corrupt_file() {
+        let directory = TestDirectory::new("recover");
+        let file_path = directory.database_file();
+        let storage = JsonFileStorage::new(&file_path);
+
+        storage.save(&bot_memory_with_user(1, "sabine")).unwrap();
+        storage.save(&bot_memory_with_user(2, "kevin")).unwrap();
+        fs::write(&file_path, "{ \"user_map\": ").unwrap();
+
+        let recovered = storage.load().unwrap().unwrap();
+        assert!(recovered
+            .managed_chats
+            .get(CHAT_ID)
+            .unwrap()
+            .user_map
+            .contains_key("1"));
+    }
+
+    #[test]
+    fn should_not_back_up_corrupt_file() {
+        let directory = TestDirectory::new("corrupt_backup");
+        let file_path = directory.database_file();
+        let storage = JsonFileStorage {
+            backup_count: 1,
+            backup_interval_secs: 0,
+            ..JsonFileStorage::new(&file_path)
+        };
+
+        storage.save(&bot_memory_with_user(1, "sabine")).unwrap();
+        storage.save(&bot_memory_with_user(2, "kevin")).unwrap();
+        fs::write(&file_path, "{ \"user_map\": ").unwrap();
+        storage.save(&bot_memory_with_user(3, "jana")).unwrap();
+
+        let backups = storage.list_backups();
+        assert_eq!(backups.len(), 1);
+        assert!(JsonFileStorage::read_file(&backups[0].1).is_ok());
+    }
+
+    #[test]
+    fn should_keep_histories_apart_and_unreadable_ones_untouched() {
+        let directory = TestDirectory::new("history");
+        let storage = JsonFileStorage::new(&directory.database_file());
+        let entries = [HistoryEntryEntity::new(
+            1,
+            ChatMessage::user("hi".to_string()),
+        )];
+
+        storage.save_history("-100", &entries).unwrap();
+        storage.save_history("-100:7", &entries).unwrap();
+        let corrupt_file_path = directory.path().join("bot_history").join("-100.json");
+        fs::write(&corrupt_file_path, "[").unwrap();
+
+        assert!(storage.load_history("-100").is_err());
+        assert!(storage.save_history("-100", &entries).is_err());
+        assert_eq!(fs::read_to_string(&corrupt_file_path).unwrap(), "[");
+        assert_eq!(storage.load_history("-100:7").unwrap().len(), 1);
+        assert!(storage.load_history("-200").unwrap().is_empty());
+    }
+}
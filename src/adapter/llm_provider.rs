@@ -0,0 +1,394 @@
+use std::{env, sync::Arc};
+
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream};
+use log::{debug, warn};
+use ollama_rs::{
+    generation::{
+        chat::{ChatMessage, MessageRole},
+        tools::ToolInfo,
+    },
+    Ollama,
+};
+use schemars::Schema;
+
+use crate::adapter::{
+    ollama_from_env, OllamaProvider, OpenAiProvider, ResilienceSettings, ResilientLlmProvider,
+};
+
+const LLM_PROVIDER_ENV: &str = "LLM_PROVIDER";
+const LLM_CONTEXT_WINDOW_ENV: &str = "LLM_CONTEXT_WINDOW";
+const LLM_CONTEXT_WINDOWS_ENV: &str = "LLM_CONTEXT_WINDOWS";
+const LLM_FALLBACKS_ENV: &str = "LLM_FALLBACKS";
+const DEFAULT_CONTEXT_WINDOW: usize = 8192;
+const LLM_THINK_ENV: &str = "LLM_THINK";
+const LLM_THINK_MODELS_ENV: &str = "LLM_THINK_MODELS";
+const DEFAULT_THINK_OPERATIONS: &str = "moderation";
+/// Share of the context window the conversation history, system prompt included, may take.
+pub const HISTORY_CONTEXT_WINDOW_DIVISOR: usize = 2;
+// Rough estimate of the tokenizers of common models
+const CHARS_PER_TOKEN: usize = 4;
+const TOKENS_PER_MESSAGE: usize = 4;
+
+/// Kinds of requests the bot sends to the LLM, thinking is configured per operation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LlmOperation {
+    Moderation,
+    Summary,
+    Introduction,
+    Compaction,
+}
+
+impl LlmOperation {
+    pub const ALL: [LlmOperation; 4] = [
+        LlmOperation::Moderation,
+        LlmOperation::Summary,
+        LlmOperation::Introduction,
+        LlmOperation::Compaction,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LlmOperation::Moderation => "moderation",
+            LlmOperation::Summary => "summary",
+            LlmOperation::Introduction => "introduction",
+            LlmOperation::Compaction => "compaction",
+        }
+    }
+}
+
+/// Decides which requests let the model think. `LLM_THINK` lists the operations, `all` or
+/// `none` (defaults to `moderation`), `LLM_THINK_MODELS` the models which support thinking
+/// by name prefix (unset for every model).
+#[derive(Clone, Debug)]
+pub struct ThinkSettings {
+    operations: Vec<LlmOperation>,
+    models: Option<Vec<String>>,
+}
+
+impl Default for ThinkSettings {
+    fn default() -> Self {
+        Self::new(DEFAULT_THINK_OPERATIONS, None)
+    }
+}
+
+impl ThinkSettings {
+    pub fn new(operations: &str, models_opt: Option<&str>) -> Self {
+        let operations: Vec<LlmOperation> = match operations.trim().to_lowercase().as_str() {
+            "all" => LlmOperation::ALL.to_vec(),
+            "none" | "" => Vec::new(),
+            names => names
+                .split(',')
+                .map(str::trim)
+                .filter_map(|name| {
+                    let operation_opt = LlmOperation::ALL
+                        .into_iter()
+                        .find(|operation| operation.name() == name);
+                    if operation_opt.is_none() {
+                        warn!("Ignoring unknown operation {} in {}", name, LLM_THINK_ENV);
+                    }
+                    operation_opt
+                })
+                .collect(),
+        };
+        let models: Option<Vec<String>> = models_opt.map(|models| {
+            models
+                .split(',')
+                .map(|model| model.trim().to_string())
+                .filter(|model| !model.is_empty())
+                .collect()
+        });
+        Self { operations, models }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(
+            &env::var(LLM_THINK_ENV).unwrap_or_else(|_| DEFAULT_THINK_OPERATIONS.to_string()),
+            env::var(LLM_THINK_MODELS_ENV).ok().as_deref(),
+        )
+    }
+
+    pub fn thinks(&self, operation: LlmOperation, model_name: &str) -> bool {
+        self.operations.contains(&operation) && self.supports_model(model_name)
+    }
+
+    pub fn supports_model(&self, model_name: &str) -> bool {
+        self.models.as_ref().is_none_or(|models| {
+            models
+                .iter()
+                .any(|model| model_name.starts_with(model.as_str()))
+        })
+    }
+}
+
+/// Context windows of the models in tokens. `LLM_CONTEXT_WINDOWS` lists them as `model=tokens`
+/// by name prefix, e.g. `qwen3=32768,mistral-nemo=16384`, the first matching prefix wins.
+/// Other models have the context window of `LLM_CONTEXT_WINDOW` (defaults to 8192).
+#[derive(Clone, Debug)]
+pub struct ContextWindows {
+    default_tokens: usize,
+    models: Vec<(String, usize)>,
+}
+
+impl Default for ContextWindows {
+    fn default() -> Self {
+        Self::new(DEFAULT_CONTEXT_WINDOW, "")
+    }
+}
+
+impl ContextWindows {
+    pub fn new(default_tokens: usize, models: &str) -> Self {
+        let models: Vec<(String, usize)> = models
+            .split(',')
+            .map(str::trim)
+            .filter(|model| !model.is_empty())
+            .filter_map(|model| {
+                let tokens_opt = model
+                    .split_once('=')
+                    .and_then(|(name, tokens)| Some((name.trim(), tokens.trim().parse().ok()?)));
+                if tokens_opt.is_none() {
+                    warn!(
+                        "Ignoring invalid entry {} in {}",
+                        model, LLM_CONTEXT_WINDOWS_ENV
+                    );
+                }
+                tokens_opt.map(|(name, tokens)| (name.to_string(), tokens))
+            })
+            .collect();
+        Self {
+            default_tokens,
+            models,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(
+            env::var(LLM_CONTEXT_WINDOW_ENV)
+                .ok()
+                .and_then(|tokens| tokens.parse().ok())
+                .unwrap_or(DEFAULT_CONTEXT_WINDOW),
+            &env::var(LLM_CONTEXT_WINDOWS_ENV).unwrap_or_default(),
+        )
+    }
+
+    pub fn context_window(&self, model_name: &str) -> usize {
+        self.models
+            .iter()
+            .find(|(model, _)| model_name.starts_with(model.as_str()))
+            .map_or(self.default_tokens, |(_, tokens)| *tokens)
+    }
+}
+
+/// Estimated number of tokens of a message.
+pub fn estimate_tokens(message: &ChatMessage) -> usize {
+    let tool_call_chars: usize = message
+        .tool_calls
+        .iter()
+        .map(|call| call.function.name.len() + call.function.arguments.to_string().len())
+        .sum();
+    (message.content.chars().count() + tool_call_chars) / CHARS_PER_TOKEN + TOKENS_PER_MESSAGE
+}
+
+/// Drops the oldest messages between the leading system prompt and the latest user message
+/// until they fit the history share of `context_window`, so a model with a smaller context
+/// window than the history is sized for gets the recent conversation only.
+pub fn fit_context_window(
+    mut messages: Vec<ChatMessage>,
+    context_window: usize,
+) -> Vec<ChatMessage> {
+    let Some(last_user_index) = messages
+        .iter()
+        .rposition(|message| message.role == MessageRole::User)
+    else {
+        return messages;
+    };
+    let first_index: usize = messages
+        .iter()
+        .position(|message| message.role != MessageRole::System)
+        .unwrap_or(last_user_index)
+        .min(last_user_index);
+    let budget: usize = context_window / HISTORY_CONTEXT_WINDOW_DIVISOR;
+    let mut tokens: usize = messages[..last_user_index]
+        .iter()
+        .map(estimate_tokens)
+        .sum();
+    let mut end_index: usize = first_index;
+    while tokens > budget && end_index < last_user_index {
+        tokens -= estimate_tokens(&messages[end_index]);
+        end_index += 1;
+        // Tool results are not sent without the tool calls they answer
+        while end_index < last_user_index && messages[end_index].role == MessageRole::Tool {
+            tokens -= estimate_tokens(&messages[end_index]);
+            end_index += 1;
+        }
+    }
+    if end_index > first_index {
+        debug!(
+            "Dropping {} messages to fit a context window of {} tokens",
+            end_index - first_index,
+            context_window
+        );
+        messages.drain(first_index..end_index);
+    }
+    messages
+}
+
+/// Options of a single chat request.
+#[derive(Clone, Debug, Default)]
+pub struct ChatOptions {
+    /// Lets reasoning models think before they answer.
+    pub think: bool,
+    /// Forces the answer to be a JSON document following this schema.
+    pub json_schema: Option<Schema>,
+    /// Tools the model may call instead of answering.
+    pub tools: Vec<ToolInfo>,
+}
+
+/// Parts of a streamed answer, concatenating their contents and tool calls gives the whole answer.
+pub type ChatMessageStream = BoxStream<'static, Result<ChatMessage, anyhow::Error>>;
+
+/// Chat backend behind the `Moderator`. Messages, tool definitions and tool calls use the
+/// `ollama_rs` types, backends speaking another protocol convert them on the fly.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Name of the model answering the requests.
+    fn model_name(&self) -> &str;
+
+    /// `false` while the backend is known to fail, requests are not worth sending then.
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    /// Sends the conversation and returns the answer of the model, which either has content
+    /// or tool calls.
+    async fn chat(
+        &self,
+        messages: Vec<ChatMessage>,
+        options: ChatOptions,
+    ) -> Result<ChatMessage, anyhow::Error>;
+
+    /// Like `chat`, but returns the answer in parts as soon as the model produces them.
+    /// Backends without streaming support return the whole answer as a single part.
+    async fn chat_stream(
+        &self,
+        messages: Vec<ChatMessage>,
+        options: ChatOptions,
+    ) -> Result<ChatMessageStream, anyhow::Error> {
+        let message = self.chat(messages, options).await?;
+        Ok(single_message_stream(message))
+    }
+}
+
+/// Stream of an answer which has been received as a whole.
+pub fn single_message_stream(message: ChatMessage) -> ChatMessageStream {
+    Box::pin(stream::once(async { Ok(message) }))
+}
+
+/// Opens the LLM backend selected by the `LLM_PROVIDER` environment variable
+/// (`ollama` or `openai`, defaults to `ollama`). `LLM_FALLBACKS` lists the models to fall back
+/// to in order, each as `model`, `model@host` or `@host` for the configured model on another
+/// host, e.g. `qwen3:4b,@http://backup:11434`.
+pub fn open_configured_llm_provider() -> Arc<dyn LlmProvider> {
+    let provider = env::var(LLM_PROVIDER_ENV).unwrap_or_else(|_| "ollama".to_string());
+    let mut backends: Vec<Arc<dyn LlmProvider>> =
+        vec![open_llm_backend(&provider, &configured_model_name(), None)];
+    let fallbacks: String = env::var(LLM_FALLBACKS_ENV).unwrap_or_default();
+    for fallback in fallbacks.split(',').map(str::trim) {
+        if fallback.is_empty() {
+            continue;
+        }
+        let (model_name, host_opt) = match fallback.split_once('@') {
+            Some((model_name, host)) => (model_name, Some(host)),
+            None => (fallback, None),
+        };
+        let model_name: String = if model_name.is_empty() {
+            configured_model_name()
+        } else {
+            model_name.to_string()
+        };
+        backends.push(open_llm_backend(&provider, &model_name, host_opt));
+    }
+    Arc::new(ResilientLlmProvider::new(
+        backends,
+        ResilienceSettings::from_env(),
+        ThinkSettings::from_env(),
+        ContextWindows::from_env(),
+    ))
+}
+
+/// A single backend, the host defaults to the configured one.
+fn open_llm_backend(
+    provider: &str,
+    model_name: &str,
+    host_opt: Option<&str>,
+) -> Arc<dyn LlmProvider> {
+    match provider.to_lowercase().as_str() {
+        "ollama" => {
+            let ollama = match host_opt {
+                Some(host) => Ollama::try_new(host)
+                    .unwrap_or_else(|e| panic!("Invalid Ollama host {}: {}", host, e)),
+                None => ollama_from_env(),
+            };
+            Arc::new(OllamaProvider::new(ollama, model_name))
+        }
+        "openai" => Arc::new(OpenAiProvider::new(host_opt, model_name)),
+        other => panic!("Unknown LLM provider: {}", other),
+    }
+}
+
+/// Model name shared by all backends.
+pub fn configured_model_name() -> String {
+    env::var("LLM_MODEL").unwrap_or(String::from("mistral-nemo:12b"))
+}
+
+/// Context window of a model in tokens, see `ContextWindows`.
+pub fn configured_context_window(model_name: &str) -> usize {
+    ContextWindows::from_env().context_window(model_name)
+}
+
+#[cfg(test)]
+mod llm_provider_test {
+    use ollama_rs::generation::chat::ChatMessage;
+
+    use crate::adapter::llm_provider::{
+        fit_context_window, ContextWindows, LlmOperation, ThinkSettings,
+    };
+
+    #[test]
+    fn should_think_per_operation_and_model() {
+        let settings = ThinkSettings::new("moderation, summary", Some("qwen3,deepseek-r1"));
+
+        assert!(settings.thinks(LlmOperation::Moderation, "qwen3:8b"));
+        assert!(settings.thinks(LlmOperation::Summary, "deepseek-r1:14b"));
+        assert!(!settings.thinks(LlmOperation::Compaction, "qwen3:8b"));
+        assert!(!settings.thinks(LlmOperation::Moderation, "mistral-nemo:12b"));
+        assert!(ThinkSettings::new("all", None).thinks(LlmOperation::Introduction, "any"));
+        assert!(!ThinkSettings::new("none", None).thinks(LlmOperation::Moderation, "any"));
+    }
+
+    #[test]
+    fn should_look_up_context_window_by_model_prefix() {
+        let context_windows = ContextWindows::new(8192, "qwen3=32768, mistral-nemo=16384, bad");
+
+        assert_eq!(context_windows.context_window("qwen3:8b"), 32768);
+        assert_eq!(context_windows.context_window("mistral-nemo:12b"), 16384);
+        assert_eq!(context_windows.context_window("llama3.2"), 8192);
+    }
+
+    #[test]
+    fn should_drop_oldest_messages_beyond_context_window() {
+        let messages = vec![
+            ChatMessage::system("You are Kate".to_string()),
+            ChatMessage::user("a".repeat(400)),
+            ChatMessage::assistant("b".repeat(400)),
+            ChatMessage::user("c".repeat(40)),
+        ];
+
+        let fitted = fit_context_window(messages.clone(), 300);
+
+        assert_eq!(fitted.len(), 3);
+        assert_eq!(fitted[0].content, "You are Kate");
+        assert_eq!(fitted[1].content, "b".repeat(400));
+        assert_eq!(fit_context_window(messages.clone(), 8192).len(), 4);
+    }
+}
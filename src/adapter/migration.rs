@@ -0,0 +1,215 @@
+use std::{error::Error, fmt};
+
+use log::{info, warn};
+use serde_json::{Map, Value};
+
+/// Schema version of the persisted `BotMemoryEntity` written by this bot version.
+pub const CURRENT_SCHEMA_VERSION: u32 = 3;
+const VERSION_KEY: &str = "version";
+/// Key of the users and administrators tracked before any chat was registered, the first chat
+/// the bot sees adopts them.
+pub const UNASSIGNED_CHAT_ID: &str = "unassigned";
+
+type Migration = fn(&mut Map<String, Value>) -> Result<(), Box<dyn Error>>;
+
+/// Ordered migration steps, the step at index `n` upgrades a document from version `n` to `n + 1`.
+const MIGRATIONS: [Migration; CURRENT_SCHEMA_VERSION as usize] =
+    [migrate_v0_to_v1, migrate_v1_to_v2, migrate_v2_to_v3];
+
+/// Raised when the persisted memory has been written by a newer bot version.
+#[derive(Debug)]
+pub struct UnsupportedSchemaVersion {
+    pub found: u32,
+    pub supported: u32,
+}
+
+impl fmt::Display for UnsupportedSchemaVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Database schema version {} was written by a newer bot version, this bot supports up to version {}",
+            self.found, self.supported
+        )
+    }
+}
+
+impl Error for UnsupportedSchemaVersion {}
+
+pub fn schema_version_of(document: &Value) -> u32 {
+    document
+        .get(VERSION_KEY)
+        .and_then(Value::as_u64)
+        .unwrap_or(0) as u32
+}
+
+/// Upgrades a raw bot memory document step by step to `CURRENT_SCHEMA_VERSION`.
+pub fn migrate(document: Value) -> Result<Value, Box<dyn Error>> {
+    let version = schema_version_of(&document);
+    if version > CURRENT_SCHEMA_VERSION {
+        return Err(Box::new(UnsupportedSchemaVersion {
+            found: version,
+            supported: CURRENT_SCHEMA_VERSION,
+        }));
+    }
+
+    let mut fields = match document {
+        Value::Object(fields) => fields,
+        _ => return Err("Bot memory is not a JSON object".into()),
+    };
+    for (step, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        info!("Migrating bot memory from version {} to {}", step, step + 1);
+        migration(&mut fields)?;
+        fields.insert(VERSION_KEY.to_string(), Value::from(step as u32 + 1));
+    }
+    Ok(Value::Object(fields))
+}
+
+/// Files written before versioning was introduced, every field must exist.
+fn migrate_v0_to_v1(fields: &mut Map<String, Value>) -> Result<(), Box<dyn Error>> {
+    fields
+        .entry("user_map")
+        .or_insert_with(|| Value::Object(Map::new()));
+    fields
+        .entry("administrators")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    fields.entry("managed_chat_id").or_insert(Value::Null);
+    Ok(())
+}
+
+/// A single managed chat becomes one entry of the managed chat map. Users and administrators
+/// tracked without any managed chat are kept under `UNASSIGNED_CHAT_ID`.
+fn migrate_v1_to_v2(fields: &mut Map<String, Value>) -> Result<(), Box<dyn Error>> {
+    let user_map = fields
+        .remove("user_map")
+        .unwrap_or_else(|| Value::Object(Map::new()));
+    let administrators = fields
+        .remove("administrators")
+        .unwrap_or_else(|| Value::Array(Vec::new()));
+    let chat_id: String = match fields.remove("managed_chat_id") {
+        Some(Value::String(chat_id)) => chat_id,
+        _ => {
+            warn!(
+                "No managed chat registered, keeping users and administrators as chat {} until a chat adopts them",
+                UNASSIGNED_CHAT_ID
+            );
+            UNASSIGNED_CHAT_ID.to_string()
+        }
+    };
+    let mut managed_chat = Map::new();
+    managed_chat.insert("user_map".to_string(), user_map);
+    managed_chat.insert("administrators".to_string(), administrators);
+    let mut managed_chats = Map::new();
+    managed_chats.insert(chat_id, Value::Object(managed_chat));
+    fields.insert("managed_chats".to_string(), Value::Object(managed_chats));
+    Ok(())
+}
+
+/// Administrators are identified by their user id instead of their username. Usernames which
+/// can't be resolved with the user map of the chat are dropped, `/init` registers them again.
+/// Users registered by `/init` keep a placeholder id and no first name until their first
+/// message, their usernames are dropped as well.
+fn migrate_v2_to_v3(fields: &mut Map<String, Value>) -> Result<(), Box<dyn Error>> {
+    let Some(managed_chats) = fields
+        .get_mut("managed_chats")
+        .and_then(Value::as_object_mut)
+    else {
+        return Ok(());
+    };
+    for (chat_id, managed_chat) in managed_chats.iter_mut() {
+        let Some(managed_chat) = managed_chat.as_object_mut() else {
+            continue;
+        };
+        let usernames: Vec<Value> = match managed_chat.remove("administrators") {
+            Some(Value::Array(usernames)) => usernames,
+            _ => Vec::new(),
+        };
+        let users: Vec<&Value> = managed_chat
+            .get("user_map")
+            .and_then(Value::as_object)
+            .map(|user_map| user_map.values().collect())
+            .unwrap_or_default();
+        let mut administrator_ids: Vec<Value> = Vec::new();
+        for username in usernames.iter().filter_map(Value::as_str) {
+            let user_id = users
+                .iter()
+                .find(|user| user.get("username").and_then(Value::as_str) == Some(username))
+                .filter(|user| {
+                    user.get("firstname")
+                        .and_then(Value::as_str)
+                        .is_some_and(|firstname| !firstname.is_empty())
+                })
+                .and_then(|user| user.get("user_id"))
+                .and_then(Value::as_i64);
+            match user_id {
+                Some(user_id) => administrator_ids.push(Value::from(user_id)),
+                None => warn!(
+                    "Administrator {} of chat {} is unknown, run /init to register it again",
+                    username, chat_id
+                ),
+            }
+        }
+        managed_chat.insert(
+            "administrators".to_string(),
+            Value::Array(administrator_ids),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod migration_test {
+    use serde_json::json;
+
+    use crate::adapter::{
+        migration::{
+            migrate, UnsupportedSchemaVersion, CURRENT_SCHEMA_VERSION, UNASSIGNED_CHAT_ID,
+        },
+        BotMemoryEntity,
+    };
+
+    #[test]
+    fn should_migrate_unversioned_memory_successfully() {
+        let legacy = json!({
+            "user_map": {
+                "1": { "user_id": 1, "username": "sabine", "firstname": "Sabine", "last_activity_unix_time": 10 },
+                "0": { "user_id": 0, "username": "placeholder_admin", "firstname": "", "last_activity_unix_time": 10 }
+            },
+            "administrators": ["sabine", "former_admin", "placeholder_admin"],
+            "managed_chat_id": "-100"
+        });
+
+        let migrated = migrate(legacy).unwrap();
+        let bot_memory: BotMemoryEntity = serde_json::from_value(migrated).unwrap();
+
+        assert_eq!(bot_memory.version, CURRENT_SCHEMA_VERSION);
+        let managed_chat = bot_memory.managed_chats.get("-100").unwrap();
+        assert_eq!(managed_chat.user_map.len(), 2);
+        assert_eq!(managed_chat.administrators, vec![1]);
+    }
+
+    #[test]
+    fn should_keep_users_of_memory_without_managed_chat() {
+        let legacy = json!({
+            "user_map": {
+                "1": { "user_id": 1, "username": "sabine", "firstname": "Sabine", "last_activity_unix_time": 10 }
+            },
+            "administrators": ["sabine"],
+            "managed_chat_id": null
+        });
+
+        let bot_memory: BotMemoryEntity = serde_json::from_value(migrate(legacy).unwrap()).unwrap();
+
+        let managed_chat = bot_memory.managed_chats.get(UNASSIGNED_CHAT_ID).unwrap();
+        assert_eq!(managed_chat.user_map.len(), 1);
+        assert_eq!(managed_chat.administrators, vec![1]);
+    }
+
+    #[test]
+    fn should_refuse_memory_of_newer_version() {
+        let newer = json!({ "version": CURRENT_SCHEMA_VERSION + 1 });
+
+        let error = migrate(newer).unwrap_err();
+
+        assert!(error.is::<UnsupportedSchemaVersion>());
+    }
+}
@@ -1,13 +1,93 @@
+mod audit_repository;
 mod database_repository;
+mod decision_repository;
 mod endpoints;
+mod history_repository;
+mod json_lines_log;
+mod json_storage;
+mod llm_provider;
+mod migration;
+mod ollama_provider;
+mod openai_provider;
+mod resilient_llm_provider;
+mod semantic_memory_repository;
+mod sqlite_storage;
+mod state_export;
+mod storage;
+mod telegram_documents;
+mod telegram_streaming;
+#[cfg(test)]
+mod test_directory;
+mod work_queue;
+mod write_behind;
+pub use audit_repository::AuditQuery;
+pub use audit_repository::AuditRepository;
+pub use database_repository::AuditEntryEntity;
 pub use database_repository::BotDatabase;
+pub use database_repository::BotMemoryEntity;
+pub use database_repository::DecisionEntity;
+pub use database_repository::HistoryEntryEntity;
+pub use database_repository::ManagedChatEntity;
+pub use database_repository::MemoryEntryEntity;
+pub use database_repository::NameChangeEntity;
+pub use database_repository::PersonaEntity;
+pub use database_repository::SanctionEntity;
 pub use database_repository::UserEntity;
+pub use database_repository::SANCTION_KINDS;
+pub use decision_repository::DecisionRepository;
 pub use endpoints::bot_greeting_action;
 pub use endpoints::chat_summarize_action;
+pub use endpoints::export_action;
 pub use endpoints::handle_chat_messages;
+pub use endpoints::import_action;
 pub use endpoints::inactive_users_action;
 pub use endpoints::init_bot;
 pub use endpoints::killswitch_action;
+pub use endpoints::modlog_action;
 pub use endpoints::mute_user_action;
+pub use endpoints::persona_action;
+pub use endpoints::register_bot_commands;
+pub use endpoints::reload_prompt_action;
+pub use endpoints::remember_action;
 pub use endpoints::unmute_user_action;
+pub use endpoints::why_action;
 pub use endpoints::BotController;
+pub use history_repository::HistoryRepository;
+pub use json_lines_log::flush_json_logs;
+pub use json_lines_log::JsonLinesLog;
+pub use json_storage::JsonFileStorage;
+pub use json_storage::FILE_DB_PATH_ENV;
+pub use llm_provider::configured_context_window;
+pub use llm_provider::estimate_tokens;
+pub use llm_provider::fit_context_window;
+pub use llm_provider::open_configured_llm_provider;
+pub use llm_provider::single_message_stream;
+pub use llm_provider::ChatMessageStream;
+pub use llm_provider::ChatOptions;
+pub use llm_provider::ContextWindows;
+pub use llm_provider::LlmOperation;
+pub use llm_provider::LlmProvider;
+pub use llm_provider::ThinkSettings;
+pub use llm_provider::HISTORY_CONTEXT_WINDOW_DIVISOR;
+pub use migration::migrate;
+pub use migration::UnsupportedSchemaVersion;
+pub use migration::CURRENT_SCHEMA_VERSION;
+pub use migration::UNASSIGNED_CHAT_ID;
+pub use ollama_provider::ollama_from_env;
+pub use ollama_provider::OllamaProvider;
+pub use openai_provider::OpenAiProvider;
+pub use resilient_llm_provider::ResilienceSettings;
+pub use resilient_llm_provider::ResilientLlmProvider;
+pub use semantic_memory_repository::SemanticMemoryRepository;
+pub use sqlite_storage::SqliteStorage;
+pub use state_export::BotStateExport;
+pub use state_export::ExportFormat;
+pub use storage::import_json_database;
+pub use storage::open_configured_storage;
+pub use storage::BotStorage;
+pub use telegram_documents::download_document;
+pub use telegram_documents::send_document;
+pub use telegram_streaming::StreamingReply;
+pub use work_queue::WorkQueue;
+pub use write_behind::flush_pending_writes;
+pub use write_behind::WriteBehindFlusher;
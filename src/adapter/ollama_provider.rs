@@ -0,0 +1,90 @@
+use std::env;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use futures::StreamExt;
+use ollama_rs::{
+    generation::{
+        chat::{request::ChatMessageRequest, ChatMessage},
+        parameters::{FormatType, JsonStructure},
+    },
+    Ollama,
+};
+
+use crate::adapter::{ChatMessageStream, ChatOptions, LlmProvider};
+
+/// Native Ollama chat API.
+pub struct OllamaProvider {
+    ollama: Ollama,
+    model_name: String,
+}
+
+/// Ollama client configured by `OLLAMA_HOST_ADDR` and `OLLAMA_PORT`.
+pub fn ollama_from_env() -> Ollama {
+    Ollama::builder()
+        .host(env::var("OLLAMA_HOST_ADDR").unwrap_or(String::from("http://localhost")))
+        .port(
+            env::var("OLLAMA_PORT")
+                .unwrap_or(String::from("11434"))
+                .parse()
+                .unwrap(),
+        )
+        .build()
+}
+
+impl OllamaProvider {
+    pub fn new(ollama: Ollama, model_name: &str) -> Self {
+        Self {
+            ollama,
+            model_name: model_name.to_string(),
+        }
+    }
+
+    fn chat_request(&self, messages: Vec<ChatMessage>, options: ChatOptions) -> ChatMessageRequest {
+        let mut request =
+            ChatMessageRequest::new(self.model_name.to_owned(), messages).think(options.think);
+        if let Some(json_schema) = options.json_schema {
+            request = request.format(FormatType::StructuredJson(Box::new(
+                JsonStructure::new_for_schema(json_schema),
+            )));
+        }
+        if !options.tools.is_empty() {
+            request = request.tools(options.tools);
+        }
+        request
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    fn model_name(&self) -> &str {
+        &self.model_name
+    }
+
+    async fn chat(
+        &self,
+        messages: Vec<ChatMessage>,
+        options: ChatOptions,
+    ) -> Result<ChatMessage, anyhow::Error> {
+        let response = self
+            .ollama
+            .send_chat_messages(self.chat_request(messages, options))
+            .await?;
+        Ok(response.message)
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: Vec<ChatMessage>,
+        options: ChatOptions,
+    ) -> Result<ChatMessageStream, anyhow::Error> {
+        let response_stream = self
+            .ollama
+            .send_chat_messages_stream(self.chat_request(messages, options))
+            .await?;
+        Ok(Box::pin(response_stream.map(|part| {
+            part.map(|response| response.message)
+                .map_err(|_| anyhow!("Ollama chat stream was interrupted"))
+        })))
+    }
+}
@@ -0,0 +1,350 @@
+use std::{collections::VecDeque, env};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use futures::stream;
+use ollama_rs::generation::{
+    chat::{ChatMessage, MessageRole},
+    tools::{ToolCall, ToolCallFunction},
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::adapter::{single_message_stream, ChatMessageStream, ChatOptions, LlmProvider};
+
+const OPENAI_BASE_URL_ENV: &str = "OPENAI_BASE_URL";
+const OPENAI_API_KEY_ENV: &str = "OPENAI_API_KEY";
+const DEFAULT_OPENAI_BASE_URL: &str = "http://localhost:8080/v1";
+
+#[derive(Deserialize)]
+struct CompletionResponse {
+    choices: Vec<CompletionChoice>,
+}
+
+#[derive(Deserialize)]
+struct CompletionChoice {
+    message: CompletionMessage,
+}
+
+#[derive(Deserialize)]
+struct CompletionMessage {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<CompletionToolCall>,
+    reasoning_content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CompletionChunk {
+    choices: Vec<CompletionChunkChoice>,
+}
+
+#[derive(Deserialize)]
+struct CompletionChunkChoice {
+    delta: CompletionDelta,
+}
+
+#[derive(Deserialize)]
+struct CompletionDelta {
+    content: Option<String>,
+    reasoning_content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CompletionToolCall {
+    function: CompletionFunction,
+}
+
+#[derive(Deserialize)]
+struct CompletionFunction {
+    name: String,
+    arguments: String,
+}
+
+/// OpenAI compatible chat completions API, as served by llama.cpp server, vLLM and others.
+pub struct OpenAiProvider {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    model_name: String,
+}
+
+impl OpenAiProvider {
+    /// Server at `base_url`, defaults to the one configured by `OPENAI_BASE_URL`.
+    pub fn new(base_url_opt: Option<&str>, model_name: &str) -> Self {
+        let base_url: String = base_url_opt.map(str::to_string).unwrap_or_else(|| {
+            env::var(OPENAI_BASE_URL_ENV).unwrap_or(String::from(DEFAULT_OPENAI_BASE_URL))
+        });
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: env::var(OPENAI_API_KEY_ENV).ok(),
+            model_name: model_name.to_string(),
+        }
+    }
+}
+
+fn role_name(role: &MessageRole) -> &'static str {
+    match role {
+        MessageRole::User => "user",
+        MessageRole::Assistant => "assistant",
+        MessageRole::System => "system",
+        MessageRole::Tool => "tool",
+    }
+}
+
+/// Converts the conversation into chat completion messages. Ollama doesn't identify tool calls,
+/// so every tool call gets an id and the following tool results are assigned in order.
+fn to_completion_messages(messages: &[ChatMessage]) -> Vec<Value> {
+    let mut open_tool_call_ids: VecDeque<String> = VecDeque::new();
+    let mut tool_call_count: usize = 0;
+    messages
+        .iter()
+        .map(|message| {
+            let mut completion_message = json!({
+                "role": role_name(&message.role),
+                "content": message.content,
+            });
+            if !message.tool_calls.is_empty() {
+                let tool_calls: Vec<Value> = message
+                    .tool_calls
+                    .iter()
+                    .map(|call| {
+                        tool_call_count += 1;
+                        let id = format!("call_{}", tool_call_count);
+                        open_tool_call_ids.push_back(id.clone());
+                        json!({
+                            "id": id,
+                            "type": "function",
+                            "function": {
+                                "name": call.function.name,
+                                "arguments": call.function.arguments.to_string(),
+                            },
+                        })
+                    })
+                    .collect();
+                completion_message["tool_calls"] = Value::Array(tool_calls);
+            }
+            if message.role == MessageRole::Tool {
+                completion_message["tool_call_id"] = Value::from(
+                    open_tool_call_ids
+                        .pop_front()
+                        .unwrap_or_else(|| format!("call_{}", tool_call_count)),
+                );
+            }
+            completion_message
+        })
+        .collect()
+}
+
+impl OpenAiProvider {
+    fn completion_request(
+        &self,
+        messages: &[ChatMessage],
+        options: &ChatOptions,
+        stream: bool,
+    ) -> reqwest::RequestBuilder {
+        let mut body = json!({
+            "model": self.model_name,
+            "messages": to_completion_messages(messages),
+            "stream": stream,
+            "chat_template_kwargs": { "enable_thinking": options.think },
+        });
+        if let Some(json_schema) = &options.json_schema {
+            body["response_format"] = json!({
+                "type": "json_schema",
+                "json_schema": { "name": "answer", "schema": json_schema },
+            });
+        }
+        if !options.tools.is_empty() {
+            body["tools"] = options
+                .tools
+                .iter()
+                .map(|tool| {
+                    json!({
+                        "type": "function",
+                        "function": {
+                            "name": tool.function.name,
+                            "description": tool.function.description,
+                            "parameters": tool.function.parameters,
+                        },
+                    })
+                })
+                .collect();
+        }
+
+        let mut request = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&body);
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        request
+    }
+}
+
+/// Splits server-sent events of a streamed chat completion into answer parts.
+#[derive(Default)]
+struct CompletionEventParser {
+    buffer: Vec<u8>,
+    done: bool,
+}
+
+impl CompletionEventParser {
+    /// Consumes a received chunk and returns the answer parts of all complete events in it.
+    fn parse(&mut self, chunk: &[u8]) -> Result<Vec<ChatMessage>, anyhow::Error> {
+        self.buffer.extend_from_slice(chunk);
+        let mut parts = Vec::new();
+        while let Some(line_end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=line_end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim().strip_prefix("data:") else {
+                continue;
+            };
+            let data = data.trim();
+            if data == "[DONE]" {
+                self.done = true;
+                continue;
+            }
+            let chunk: CompletionChunk = serde_json::from_str(data)?;
+            for choice in chunk.choices {
+                let mut part = ChatMessage::assistant(choice.delta.content.unwrap_or_default());
+                part.thinking = choice.delta.reasoning_content;
+                parts.push(part);
+            }
+        }
+        Ok(parts)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn model_name(&self) -> &str {
+        &self.model_name
+    }
+
+    async fn chat(
+        &self,
+        messages: Vec<ChatMessage>,
+        options: ChatOptions,
+    ) -> Result<ChatMessage, anyhow::Error> {
+        let request = self.completion_request(&messages, &options, false);
+        let response: CompletionResponse = request.send().await?.error_for_status()?.json().await?;
+        let completion = response
+            .choices
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("Chat completion contains no choice"))?
+            .message;
+
+        let mut message = ChatMessage::assistant(completion.content.unwrap_or_default());
+        message.thinking = completion.reasoning_content;
+        for call in completion.tool_calls {
+            message.tool_calls.push(ToolCall {
+                function: ToolCallFunction {
+                    name: call.function.name,
+                    arguments: serde_json::from_str(&call.function.arguments)?,
+                },
+            });
+        }
+        Ok(message)
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: Vec<ChatMessage>,
+        options: ChatOptions,
+    ) -> Result<ChatMessageStream, anyhow::Error> {
+        // Streamed tool calls arrive in fragments, answers with tools are received as a whole
+        if !options.tools.is_empty() {
+            let message = self.chat(messages, options).await?;
+            return Ok(single_message_stream(message));
+        }
+        let response = self
+            .completion_request(&messages, &options, true)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let parts = stream::unfold(
+            (response, CompletionEventParser::default(), VecDeque::new()),
+            |(mut response, mut parser, mut pending)| async move {
+                loop {
+                    if let Some(part) = pending.pop_front() {
+                        return Some((Ok(part), (response, parser, pending)));
+                    }
+                    if parser.done {
+                        return None;
+                    }
+                    let chunk = match response.chunk().await {
+                        Ok(Some(chunk)) => chunk,
+                        Ok(None) => return None,
+                        Err(e) => {
+                            parser.done = true;
+                            return Some((Err(e.into()), (response, parser, pending)));
+                        }
+                    };
+                    match parser.parse(&chunk) {
+                        Ok(parts) => pending.extend(parts),
+                        Err(e) => {
+                            parser.done = true;
+                            return Some((Err(e), (response, parser, pending)));
+                        }
+                    }
+                }
+            },
+        );
+        Ok(Box::pin(parts))
+    }
+}
+
+#[cfg(test)]
+mod openai_provider_test {
+    use ollama_rs::generation::{
+        chat::ChatMessage,
+        tools::{ToolCall, ToolCallFunction},
+    };
+    use serde_json::json;
+
+    use crate::adapter::openai_provider::{to_completion_messages, CompletionEventParser};
+
+    #[test]
+    fn should_assign_tool_results_to_tool_calls() {
+        let mut tool_call_message = ChatMessage::assistant(String::new());
+        tool_call_message.tool_calls.push(ToolCall {
+            function: ToolCallFunction {
+                name: "web_search".to_string(),
+                arguments: json!({ "query": "rust" }),
+            },
+        });
+        let messages = vec![
+            ChatMessage::user("Search for rust".to_string()),
+            tool_call_message,
+            ChatMessage::tool("Rust is a language".to_string()),
+        ];
+
+        let completion_messages = to_completion_messages(&messages);
+
+        assert_eq!(completion_messages[1]["tool_calls"][0]["id"], "call_1");
+        assert_eq!(
+            completion_messages[1]["tool_calls"][0]["function"]["arguments"],
+            "{\"query\":\"rust\"}"
+        );
+        assert_eq!(completion_messages[2]["tool_call_id"], "call_1");
+    }
+
+    #[test]
+    fn should_parse_events_split_across_chunks() {
+        let mut parser = CompletionEventParser::default();
+        let events = "data: {\"choices\":[{\"delta\":{\"content\":\"Hal\"}}]}\n\ndata: {\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}\n\ndata: [DONE]\n\n";
+        let (first_chunk, second_chunk) = events.as_bytes().split_at(60);
+
+        let mut parts = parser.parse(first_chunk).unwrap();
+        parts.extend(parser.parse(second_chunk).unwrap());
+
+        let contents: Vec<String> = parts.into_iter().map(|part| part.content).collect();
+        assert_eq!(contents, vec!["Hal", "lo"]);
+        assert!(parser.done);
+    }
+}
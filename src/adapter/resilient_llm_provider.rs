@@ -0,0 +1,430 @@
+use std::{
+    env,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use futures::{stream, StreamExt};
+use log::warn;
+use ollama_rs::generation::chat::ChatMessage;
+
+use crate::adapter::{
+    fit_context_window, ChatMessageStream, ChatOptions, ContextWindows, LlmProvider, ThinkSettings,
+};
+
+const LLM_TIMEOUT_SECS_ENV: &str = "LLM_TIMEOUT_SECS";
+const LLM_RETRIES_ENV: &str = "LLM_RETRIES";
+const LLM_RETRY_BACKOFF_MILLIS_ENV: &str = "LLM_RETRY_BACKOFF_MILLIS";
+const LLM_CIRCUIT_FAILURES_ENV: &str = "LLM_CIRCUIT_FAILURES";
+const LLM_CIRCUIT_OPEN_SECS_ENV: &str = "LLM_CIRCUIT_OPEN_SECS";
+const DEFAULT_TIMEOUT_SECS: u64 = 120;
+const DEFAULT_RETRIES: u32 = 2;
+const DEFAULT_RETRY_BACKOFF_MILLIS: u64 = 500;
+const DEFAULT_CIRCUIT_FAILURES: u32 = 3;
+const DEFAULT_CIRCUIT_OPEN_SECS: u64 = 60;
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Timeouts, retries and circuit breaker thresholds of the LLM calls.
+#[derive(Clone, Debug)]
+pub struct ResilienceSettings {
+    /// Limit of a request and, for streamed answers, of the wait for the next part.
+    pub timeout: Duration,
+    /// Further attempts on the same backend before falling back to the next one.
+    pub retries: u32,
+    /// Wait before the first retry, doubled with every further retry.
+    pub retry_backoff: Duration,
+    /// Failed calls in a row after which a backend is skipped.
+    pub circuit_failures: u32,
+    /// How long a backend is skipped before it gets another chance.
+    pub circuit_open_duration: Duration,
+}
+
+impl ResilienceSettings {
+    pub fn from_env() -> Self {
+        Self {
+            timeout: Duration::from_secs(env_or(LLM_TIMEOUT_SECS_ENV, DEFAULT_TIMEOUT_SECS)),
+            retries: env_or(LLM_RETRIES_ENV, DEFAULT_RETRIES),
+            retry_backoff: Duration::from_millis(env_or(
+                LLM_RETRY_BACKOFF_MILLIS_ENV,
+                DEFAULT_RETRY_BACKOFF_MILLIS,
+            )),
+            circuit_failures: env_or(LLM_CIRCUIT_FAILURES_ENV, DEFAULT_CIRCUIT_FAILURES).max(1),
+            circuit_open_duration: Duration::from_secs(env_or(
+                LLM_CIRCUIT_OPEN_SECS_ENV,
+                DEFAULT_CIRCUIT_OPEN_SECS,
+            )),
+        }
+    }
+}
+
+#[derive(Default)]
+struct CircuitState {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    probe_in_flight: bool,
+}
+
+/// Skips a backend after repeated failures. Once the open duration has passed the circuit is
+/// half-open, a single probe call is let through while the other calls still skip the backend.
+/// A successful probe closes the circuit, a failed one opens it again.
+struct CircuitBreaker {
+    failure_threshold: u32,
+    open_duration: Duration,
+    state: Mutex<CircuitState>,
+}
+
+impl CircuitBreaker {
+    fn new(failure_threshold: u32, open_duration: Duration) -> Self {
+        Self {
+            failure_threshold,
+            open_duration,
+            state: Mutex::new(CircuitState::default()),
+        }
+    }
+
+    /// Whether a call would be admitted right now, without admitting it.
+    fn allows_request(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.opened_at.is_none_or(|opened_at| {
+            opened_at.elapsed() >= self.open_duration && !state.probe_in_flight
+        })
+    }
+
+    /// Admits a call, in the half-open state only the first one as the probe. The probe is
+    /// released when the returned admission is dropped, even if the call is cancelled.
+    fn admit(&self) -> Option<Admission<'_>> {
+        let mut state = self.state.lock().unwrap();
+        match state.opened_at {
+            None => Some(Admission {
+                circuit_breaker: self,
+                is_probe: false,
+            }),
+            Some(opened_at)
+                if opened_at.elapsed() >= self.open_duration && !state.probe_in_flight =>
+            {
+                state.probe_in_flight = true;
+                Some(Admission {
+                    circuit_breaker: self,
+                    is_probe: true,
+                })
+            }
+            Some(_) => None,
+        }
+    }
+
+    fn record_success(&self) {
+        *self.state.lock().unwrap() = CircuitState::default();
+    }
+
+    fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= self.failure_threshold {
+            state.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+/// A call admitted by a `CircuitBreaker`.
+struct Admission<'a> {
+    circuit_breaker: &'a CircuitBreaker,
+    is_probe: bool,
+}
+
+impl Drop for Admission<'_> {
+    fn drop(&mut self) {
+        if self.is_probe {
+            self.circuit_breaker.state.lock().unwrap().probe_in_flight = false;
+        }
+    }
+}
+
+struct Backend {
+    provider: Arc<dyn LlmProvider>,
+    circuit_breaker: CircuitBreaker,
+}
+
+/// Sends requests to the first available of an ordered list of backends, the configured model
+/// followed by the fallback models or hosts. Failed calls are retried with exponential backoff
+/// before the next backend is tried, backends failing repeatedly are skipped for a while.
+pub struct ResilientLlmProvider {
+    backends: Vec<Backend>,
+    settings: ResilienceSettings,
+    think_settings: ThinkSettings,
+    context_windows: ContextWindows,
+}
+
+impl ResilientLlmProvider {
+    pub fn new(
+        providers: Vec<Arc<dyn LlmProvider>>,
+        settings: ResilienceSettings,
+        think_settings: ThinkSettings,
+        context_windows: ContextWindows,
+    ) -> Self {
+        let backends: Vec<Backend> = providers
+            .into_iter()
+            .map(|provider| Backend {
+                provider,
+                circuit_breaker: CircuitBreaker::new(
+                    settings.circuit_failures,
+                    settings.circuit_open_duration,
+                ),
+            })
+            .collect();
+        Self {
+            backends,
+            settings,
+            think_settings,
+            context_windows,
+        }
+    }
+
+    /// Options for a backend, fallback models which don't support thinking answer without.
+    fn backend_options(&self, backend: &Backend, options: &ChatOptions) -> ChatOptions {
+        ChatOptions {
+            think: options.think
+                && self
+                    .think_settings
+                    .supports_model(backend.provider.model_name()),
+            ..options.clone()
+        }
+    }
+
+    /// Runs `call` on the available backends in order until it succeeds. Every backend gets
+    /// the messages fitted to its context window and its retries, a backend whose retries all
+    /// failed counts one failure for its circuit.
+    async fn call_backends<T, F, Fut>(
+        &self,
+        messages: Vec<ChatMessage>,
+        options: ChatOptions,
+        call: F,
+    ) -> Result<T, anyhow::Error>
+    where
+        F: Fn(Arc<dyn LlmProvider>, Vec<ChatMessage>, ChatOptions) -> Fut + Send + Sync,
+        Fut: Future<Output = Result<T, anyhow::Error>> + Send,
+        T: Send,
+    {
+        let mut last_error: anyhow::Error = anyhow!("No LLM backend is available");
+        for backend in &self.backends {
+            let Some(_admission) = backend.circuit_breaker.admit() else {
+                continue;
+            };
+            let backend_options: ChatOptions = self.backend_options(backend, &options);
+            let backend_messages: Vec<ChatMessage> = fit_context_window(
+                messages.clone(),
+                self.context_windows
+                    .context_window(backend.provider.model_name()),
+            );
+            for attempt in 0..=self.settings.retries {
+                if attempt > 0 {
+                    let backoff: Duration = self
+                        .settings
+                        .retry_backoff
+                        .saturating_mul(2u32.saturating_pow(attempt - 1));
+                    tokio::time::sleep(backoff).await;
+                }
+                let call_rs = tokio::time::timeout(
+                    self.settings.timeout,
+                    call(
+                        backend.provider.clone(),
+                        backend_messages.clone(),
+                        backend_options.clone(),
+                    ),
+                )
+                .await
+                .unwrap_or_else(|_| Err(anyhow!("No answer within {:?}", self.settings.timeout)));
+                match call_rs {
+                    Ok(result) => {
+                        backend.circuit_breaker.record_success();
+                        return Ok(result);
+                    }
+                    Err(e) => {
+                        warn!(
+                            "LLM call to {} failed in attempt {} of {} cause: {}",
+                            backend.provider.model_name(),
+                            attempt + 1,
+                            self.settings.retries + 1,
+                            e
+                        );
+                        last_error = e;
+                    }
+                }
+            }
+            backend.circuit_breaker.record_failure();
+        }
+        Err(last_error)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for ResilientLlmProvider {
+    fn model_name(&self) -> &str {
+        self.backends
+            .first()
+            .map_or("", |backend| backend.provider.model_name())
+    }
+
+    fn is_available(&self) -> bool {
+        self.backends
+            .iter()
+            .any(|backend| backend.circuit_breaker.allows_request())
+    }
+
+    async fn chat(
+        &self,
+        messages: Vec<ChatMessage>,
+        options: ChatOptions,
+    ) -> Result<ChatMessage, anyhow::Error> {
+        self.call_backends(
+            messages,
+            options,
+            |provider, messages, options| async move { provider.chat(messages, options).await },
+        )
+        .await
+    }
+
+    /// Only opening the stream is retried, parts which have been received may have been shown
+    /// already. A stream which stalls longer than the timeout ends with an error.
+    async fn chat_stream(
+        &self,
+        messages: Vec<ChatMessage>,
+        options: ChatOptions,
+    ) -> Result<ChatMessageStream, anyhow::Error> {
+        let parts: ChatMessageStream = self
+            .call_backends(
+                messages,
+                options,
+                |provider, messages, options| async move {
+                    provider.chat_stream(messages, options).await
+                },
+            )
+            .await?;
+        let timeout: Duration = self.settings.timeout;
+        let guarded_parts = stream::unfold(Some(parts), move |parts_opt| async move {
+            let mut parts: ChatMessageStream = parts_opt?;
+            match tokio::time::timeout(timeout, parts.next()).await {
+                Ok(Some(part)) => Some((part, Some(parts))),
+                Ok(None) => None,
+                Err(_) => Some((Err(anyhow!("LLM stream stalled for {:?}", timeout)), None)),
+            }
+        });
+        Ok(Box::pin(guarded_parts))
+    }
+}
+
+#[cfg(test)]
+mod resilient_llm_provider_test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use anyhow::anyhow;
+    use async_trait::async_trait;
+    use ollama_rs::generation::chat::ChatMessage;
+
+    use crate::adapter::resilient_llm_provider::{
+        CircuitBreaker, ResilienceSettings, ResilientLlmProvider,
+    };
+    use crate::adapter::{ChatOptions, ContextWindows, LlmProvider, ThinkSettings};
+
+    struct FakeProvider {
+        model_name: String,
+        healthy: bool,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl LlmProvider for FakeProvider {
+        fn model_name(&self) -> &str {
+            &self.model_name
+        }
+
+        async fn chat(
+            &self,
+            _messages: Vec<ChatMessage>,
+            options: ChatOptions,
+        ) -> Result<ChatMessage, anyhow::Error> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if !self.healthy {
+                return Err(anyhow!("connection refused"));
+            }
+            Ok(ChatMessage::assistant(format!(
+                "{} think={}",
+                self.model_name, options.think
+            )))
+        }
+    }
+
+    fn fake_provider(model_name: &str, healthy: bool) -> Arc<FakeProvider> {
+        Arc::new(FakeProvider {
+            model_name: model_name.to_string(),
+            healthy,
+            calls: AtomicUsize::new(0),
+        })
+    }
+
+    #[tokio::test]
+    async fn should_fall_back_and_open_circuit_of_failing_backend() {
+        let primary = fake_provider("qwen3:8b", false);
+        let fallback = fake_provider("mistral-nemo:12b", true);
+        let provider = ResilientLlmProvider::new(
+            vec![primary.clone(), fallback.clone()],
+            ResilienceSettings {
+                timeout: Duration::from_secs(1),
+                retries: 1,
+                retry_backoff: Duration::from_millis(1),
+                circuit_failures: 2,
+                circuit_open_duration: Duration::from_secs(60),
+            },
+            ThinkSettings::new("moderation", Some("qwen3")),
+            ContextWindows::default(),
+        );
+        let options = ChatOptions {
+            think: true,
+            ..ChatOptions::default()
+        };
+
+        for _ in 0..3 {
+            let answer = provider.chat(Vec::new(), options.clone()).await.unwrap();
+            assert_eq!(answer.content, "mistral-nemo:12b think=false");
+        }
+
+        // Two failed calls with one retry each, then the circuit of the primary is open
+        assert_eq!(primary.calls.load(Ordering::SeqCst), 4);
+        assert_eq!(fallback.calls.load(Ordering::SeqCst), 3);
+        assert!(provider.is_available());
+    }
+
+    #[test]
+    fn should_admit_a_single_probe_once_the_circuit_is_half_open() {
+        let circuit_breaker = CircuitBreaker::new(1, Duration::ZERO);
+        circuit_breaker.record_failure();
+
+        let probe = circuit_breaker.admit();
+        assert!(probe.is_some());
+        assert!(circuit_breaker.admit().is_none());
+        assert!(!circuit_breaker.allows_request());
+
+        // A cancelled probe makes room for the next one
+        drop(probe);
+        assert!(circuit_breaker.allows_request());
+        let probe = circuit_breaker.admit();
+        circuit_breaker.record_success();
+        drop(probe);
+        assert!(circuit_breaker.admit().is_some());
+        assert!(circuit_breaker.admit().is_some());
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    collections::HashMap,
+    env,
+    sync::{Arc, Mutex},
+};
+
+use log::{debug, error};
+use ollama_rs::{
+    generation::embeddings::request::{EmbeddingsInput, GenerateEmbeddingsRequest},
+    Ollama,
+};
+
+use crate::adapter::{ollama_from_env, open_configured_storage, BotStorage, MemoryEntryEntity};
+
+const EMBEDDING_MODEL_ENV: &str = "EMBEDDING_MODEL";
+const MIN_MEMORY_SIMILARITY_ENV: &str = "MEMORY_MIN_SIMILARITY";
+const DEFAULT_MIN_MEMORY_SIMILARITY: f32 = 0.6;
+
+/// Long-term memory of the managed chats. Chat messages and admin-provided facts are embedded
+/// through the Ollama embeddings endpoint and stored next to the bot memory, the memory of a
+/// chat is loaded once and searched by cosine similarity.
+#[derive(Clone)]
+pub struct SemanticMemoryRepository {
+    storage: Arc<dyn BotStorage>,
+    ollama: Ollama,
+    model_name: String,
+    min_similarity: f32,
+    chat_memories: Arc<Mutex<HashMap<String, Vec<MemoryEntryEntity>>>>,
+}
+
+impl SemanticMemoryRepository {
+    /// Opens the semantic memory, `None` if no `EMBEDDING_MODEL` is configured.
+    pub fn try_init() -> Option<Self> {
+        let model_name = env::var(EMBEDDING_MODEL_ENV).ok()?;
+        Some(Self {
+            storage: open_configured_storage(),
+            ollama: ollama_from_env(),
+            model_name,
+            min_similarity: env::var(MIN_MEMORY_SIMILARITY_ENV)
+                .ok()
+                .and_then(|similarity| similarity.parse().ok())
+                .unwrap_or(DEFAULT_MIN_MEMORY_SIMILARITY),
+            chat_memories: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    pub async fn embed(&self, text: &str) -> Result<Vec<f32>, anyhow::Error> {
+        let response = self
+            .ollama
+            .generate_embeddings(GenerateEmbeddingsRequest::new(
+                self.model_name.clone(),
+                EmbeddingsInput::Single(text.to_string()),
+            ))
+            .await?;
+        response
+            .embeddings
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Embeddings response contains no embedding"))
+    }
+
+    fn with_chat_memory<T>(
+        &self,
+        chat_id: &str,
+        f: impl FnOnce(&mut Vec<MemoryEntryEntity>) -> T,
+    ) -> T {
+        let mut chat_memories = self.chat_memories.lock().unwrap();
+        let chat_memory = chat_memories.entry(chat_id.to_string()).or_insert_with(|| {
+            match self.storage.load_memory(chat_id) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    error!("Could not load semantic memory of {} cause: {}", chat_id, e);
+                    Vec::new()
+                }
+            }
+        });
+        f(chat_memory)
+    }
+
+    pub fn remember(&self, entry: MemoryEntryEntity) {
+        // The chat memory is loaded before the append, otherwise it would contain the entry twice
+        self.with_chat_memory(&entry.chat_id.clone(), |chat_memory| {
+            if let Err(e) = self.storage.append_memory(&entry) {
+                error!("Could not write semantic memory entry cause: {}", e);
+            }
+            chat_memory.push(entry)
+        });
+    }
+
+    /// Entries of a chat most similar to `embedding`, most similar first. Entries created
+    /// after `before_unix_time` are skipped, they are still part of the conversation history.
+    pub fn search(
+        &self,
+        chat_id: &str,
+        embedding: &[f32],
+        before_unix_time: u64,
+        limit: usize,
+    ) -> Vec<MemoryEntryEntity> {
+        let mut matches: Vec<(f32, MemoryEntryEntity)> =
+            self.with_chat_memory(chat_id, |chat_memory| {
+                chat_memory
+                    .iter()
+                    .filter(|entry| entry.created_unix_time < before_unix_time)
+                    .map(|entry| (cosine_similarity(&entry.embedding, embedding), entry))
+                    .filter(|(similarity, _)| *similarity >= self.min_similarity)
+                    .map(|(similarity, entry)| (similarity, entry.clone()))
+                    .collect()
+            });
+        matches.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        debug!(
+            "Found {} relevant memories in chat {}",
+            matches.len(),
+            chat_id
+        );
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|(_, entry)| entry)
+            .collect()
+    }
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+#[cfg(test)]
+mod semantic_memory_repository_test {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use ollama_rs::Ollama;
+
+    use crate::adapter::{
+        semantic_memory_repository::cosine_similarity, test_directory::TestDirectory,
+        JsonFileStorage, MemoryEntryEntity, SemanticMemoryRepository,
+    };
+
+    #[test]
+    fn should_rank_similar_embeddings_higher() {
+        let query = [1.0, 0.0, 1.0];
+
+        let similar = cosine_similarity(&query, &[0.9, 0.1, 1.0]);
+        let unrelated = cosine_similarity(&query, &[0.0, 1.0, 0.0]);
+
+        assert!(similar > 0.9);
+        assert_eq!(unrelated, 0.0);
+        assert_eq!(cosine_similarity(&query, &[1.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn should_remember_entry_of_unloaded_chat_once() {
+        let directory = TestDirectory::new("semantic_memory");
+        let semantic_memory = SemanticMemoryRepository {
+            storage: Arc::new(JsonFileStorage::new(&directory.database_file())),
+            ollama: Ollama::default(),
+            model_name: String::new(),
+            min_similarity: 0.6,
+            chat_memories: Arc::new(Mutex::new(HashMap::new())),
+        };
+
+        semantic_memory.remember(MemoryEntryEntity::new(
+            "-100",
+            "sabine",
+            "fact",
+            "The meetup is on Friday",
+            vec![1.0, 0.0],
+        ));
+
+        assert_eq!(
+            semantic_memory
+                .search("-100", &[1.0, 0.0], u64::MAX, 5)
+                .len(),
+            1
+        );
+    }
+}
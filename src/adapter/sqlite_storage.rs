@@ -0,0 +1,334 @@
+use std::{env, error::Error, sync::Mutex};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{Map, Value};
+
+use crate::adapter::{
+    migrate, AuditEntryEntity, BotMemoryEntity, BotStorage, DecisionEntity, HistoryEntryEntity,
+    MemoryEntryEntity, FILE_DB_PATH_ENV,
+};
+
+const SQLITE_FILE_NAME: &str = "bot_db.sqlite";
+const SQLITE_FILE_ENV: &str = "SQLITE_DB_FILE";
+const USER_MAP_KEY: &str = "user_map";
+const MANAGED_CHATS_KEY: &str = "managed_chats";
+/// Users table of schema version 1, when the bot managed a single chat only.
+const LEGACY_USERS_TABLE: &str = "users";
+
+/// Embedded SQLite store. Users are kept one row per chat and user so activity updates only
+/// touch a single record. The remaining fields of a managed chat are stored per chat, every
+/// other top level field of the bot memory is stored as JSON entry.
+/// Conversation history snapshots are kept one row per history key, the moderation audit log,
+/// the semantic memory and the decision log one row per entry.
+pub struct SqliteStorage {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open(file_path: &str) -> Result<Self, Box<dyn Error>> {
+        let connection = Connection::open(file_path)?;
+        connection.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS entries (key TEXT PRIMARY KEY, data TEXT NOT NULL);
+             CREATE TABLE IF NOT EXISTS chats (chat_id TEXT PRIMARY KEY, data TEXT NOT NULL);
+             CREATE TABLE IF NOT EXISTS chat_users (
+                 chat_id TEXT NOT NULL,
+                 user_id TEXT NOT NULL,
+                 data TEXT NOT NULL,
+                 PRIMARY KEY (chat_id, user_id)
+             );
+             CREATE TABLE IF NOT EXISTS history (key TEXT PRIMARY KEY, data TEXT NOT NULL);
+             CREATE TABLE IF NOT EXISTS audit (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 chat_id TEXT NOT NULL,
+                 data TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS audit_chat_id ON audit (chat_id);
+             CREATE TABLE IF NOT EXISTS semantic_memory (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 chat_id TEXT NOT NULL,
+                 data TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS semantic_memory_chat_id ON semantic_memory (chat_id);
+             CREATE TABLE IF NOT EXISTS decisions (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 chat_id TEXT NOT NULL,
+                 data TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS decisions_chat_id ON decisions (chat_id);",
+        )?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let file_path = env::var(SQLITE_FILE_ENV).unwrap_or_else(|_| {
+            let db_path = env::var(FILE_DB_PATH_ENV).unwrap_or_else(|_| "./".to_string());
+            format!("{}{}", db_path, SQLITE_FILE_NAME)
+        });
+        Self::open(&file_path)
+    }
+}
+
+fn query_pairs(
+    connection: &Connection,
+    sql: &str,
+) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let mut stmt = connection.prepare(sql)?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(rows.collect::<Result<Vec<(String, String)>, _>>()?)
+}
+
+/// Returns the JSON object of a managed chat, with an (empty) user map in any case.
+fn managed_chat_object<'a>(
+    managed_chats: &'a mut Map<String, Value>,
+    chat_id: &str,
+) -> &'a mut Map<String, Value> {
+    let managed_chat = managed_chats
+        .entry(chat_id.to_string())
+        .or_insert_with(|| Value::Object(Map::new()));
+    if !managed_chat.is_object() {
+        *managed_chat = Value::Object(Map::new());
+    }
+    let managed_chat = managed_chat.as_object_mut().unwrap();
+    managed_chat
+        .entry(USER_MAP_KEY)
+        .or_insert_with(|| Value::Object(Map::new()));
+    managed_chat
+}
+
+impl BotStorage for SqliteStorage {
+    fn load(&self) -> Result<Option<BotMemoryEntity>, Box<dyn Error>> {
+        let connection = self.connection.lock().map_err(|e| e.to_string())?;
+        let entries = query_pairs(&connection, "SELECT key, data FROM entries")?;
+        if entries.is_empty() {
+            return Ok(None);
+        }
+
+        let mut document = Map::new();
+        for (key, data) in entries {
+            document.insert(key, serde_json::from_str(&data)?);
+        }
+
+        let legacy_table: Option<String> = connection
+            .query_row(
+                "SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                params![LEGACY_USERS_TABLE],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if legacy_table.is_some() {
+            let mut user_map = Map::new();
+            for (user_id, data) in query_pairs(&connection, "SELECT user_id, data FROM users")? {
+                user_map.insert(user_id, serde_json::from_str(&data)?);
+            }
+            document.insert(USER_MAP_KEY.to_string(), Value::Object(user_map));
+        }
+
+        let mut managed_chats = Map::new();
+        for (chat_id, data) in query_pairs(&connection, "SELECT chat_id, data FROM chats")? {
+            managed_chats.insert(chat_id.clone(), serde_json::from_str(&data)?);
+            managed_chat_object(&mut managed_chats, &chat_id);
+        }
+        let mut users_stmt = connection.prepare("SELECT chat_id, user_id, data FROM chat_users")?;
+        let users = users_stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        for user in users {
+            let (chat_id, user_id, data) = user?;
+            if let Some(user_map) = managed_chat_object(&mut managed_chats, &chat_id)
+                .get_mut(USER_MAP_KEY)
+                .and_then(Value::as_object_mut)
+            {
+                user_map.insert(user_id, serde_json::from_str(&data)?);
+            }
+        }
+        document.insert(MANAGED_CHATS_KEY.to_string(), Value::Object(managed_chats));
+
+        let bot_memory = serde_json::from_value(migrate(Value::Object(document))?)?;
+        Ok(Some(bot_memory))
+    }
+
+    fn save(&self, bot_memory: &BotMemoryEntity) -> Result<(), Box<dyn Error>> {
+        let mut document = match serde_json::to_value(bot_memory)? {
+            Value::Object(document) => document,
+            _ => return Err("Bot memory is not a JSON object".into()),
+        };
+        document.remove(MANAGED_CHATS_KEY);
+
+        let mut connection = self.connection.lock().map_err(|e| e.to_string())?;
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM entries", [])?;
+        for (key, value) in document {
+            transaction.execute(
+                "INSERT INTO entries (key, data) VALUES (?1, ?2)",
+                params![key, value.to_string()],
+            )?;
+        }
+        transaction.execute("DELETE FROM chats", [])?;
+        transaction.execute("DELETE FROM chat_users", [])?;
+        for (chat_id, managed_chat) in &bot_memory.managed_chats {
+            let mut chat_document = serde_json::to_value(managed_chat)?;
+            if let Some(chat_fields) = chat_document.as_object_mut() {
+                chat_fields.remove(USER_MAP_KEY);
+            }
+            transaction.execute(
+                "INSERT INTO chats (chat_id, data) VALUES (?1, ?2)",
+                params![chat_id, chat_document.to_string()],
+            )?;
+            for (user_id, user) in &managed_chat.user_map {
+                transaction.execute(
+                    "INSERT INTO chat_users (chat_id, user_id, data) VALUES (?1, ?2, ?3)",
+                    params![chat_id, user_id, serde_json::to_string(user)?],
+                )?;
+            }
+        }
+        transaction.execute(&format!("DROP TABLE IF EXISTS {}", LEGACY_USERS_TABLE), [])?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn save_user(
+        &self,
+        bot_memory: &BotMemoryEntity,
+        chat_id: &str,
+        user_id: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let connection = self.connection.lock().map_err(|e| e.to_string())?;
+        let is_chat_stored: Option<i64> = connection
+            .query_row(
+                "SELECT 1 FROM chats WHERE chat_id = ?1",
+                params![chat_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if is_chat_stored.is_none() {
+            drop(connection);
+            return self.save(bot_memory);
+        }
+        let user_opt = bot_memory
+            .managed_chats
+            .get(chat_id)
+            .and_then(|managed_chat| managed_chat.user_map.get(user_id));
+        match user_opt {
+            Some(user) => connection.execute(
+                "INSERT INTO chat_users (chat_id, user_id, data) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(chat_id, user_id) DO UPDATE SET data = excluded.data",
+                params![chat_id, user_id, serde_json::to_string(user)?],
+            )?,
+            None => connection.execute(
+                "DELETE FROM chat_users WHERE chat_id = ?1 AND user_id = ?2",
+                params![chat_id, user_id],
+            )?,
+        };
+        Ok(())
+    }
+
+    fn save_users(
+        &self,
+        bot_memory: &BotMemoryEntity,
+        users: &[(String, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        for (chat_id, user_id) in users {
+            self.save_user(bot_memory, chat_id, user_id)?;
+        }
+        Ok(())
+    }
+
+    fn load_history(&self, key: &str) -> Result<Vec<HistoryEntryEntity>, Box<dyn Error>> {
+        let connection = self.connection.lock().map_err(|e| e.to_string())?;
+        let data: Option<String> = connection
+            .query_row(
+                "SELECT data FROM history WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match data {
+            Some(data) => Ok(serde_json::from_str(&data)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn save_history(
+        &self,
+        key: &str,
+        entries: &[HistoryEntryEntity],
+    ) -> Result<(), Box<dyn Error>> {
+        let connection = self.connection.lock().map_err(|e| e.to_string())?;
+        connection.execute(
+            "INSERT INTO history (key, data) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET data = excluded.data",
+            params![key, serde_json::to_string(entries)?],
+        )?;
+        Ok(())
+    }
+
+    fn append_audit(&self, entry: &AuditEntryEntity) -> Result<(), Box<dyn Error>> {
+        let connection = self.connection.lock().map_err(|e| e.to_string())?;
+        connection.execute(
+            "INSERT INTO audit (chat_id, data) VALUES (?1, ?2)",
+            params![entry.chat_id, serde_json::to_string(entry)?],
+        )?;
+        Ok(())
+    }
+
+    fn load_audit(&self, chat_id: &str) -> Result<Vec<AuditEntryEntity>, Box<dyn Error>> {
+        let connection = self.connection.lock().map_err(|e| e.to_string())?;
+        let mut stmt =
+            connection.prepare("SELECT data FROM audit WHERE chat_id = ?1 ORDER BY id")?;
+        let rows = stmt.query_map(params![chat_id], |row| row.get::<_, String>(0))?;
+        let mut entries = Vec::new();
+        for data in rows {
+            entries.push(serde_json::from_str(&data?)?);
+        }
+        Ok(entries)
+    }
+
+    fn append_memory(&self, entry: &MemoryEntryEntity) -> Result<(), Box<dyn Error>> {
+        let connection = self.connection.lock().map_err(|e| e.to_string())?;
+        connection.execute(
+            "INSERT INTO semantic_memory (chat_id, data) VALUES (?1, ?2)",
+            params![entry.chat_id, serde_json::to_string(entry)?],
+        )?;
+        Ok(())
+    }
+
+    fn load_memory(&self, chat_id: &str) -> Result<Vec<MemoryEntryEntity>, Box<dyn Error>> {
+        let connection = self.connection.lock().map_err(|e| e.to_string())?;
+        let mut stmt = connection
+            .prepare("SELECT data FROM semantic_memory WHERE chat_id = ?1 ORDER BY id")?;
+        let rows = stmt.query_map(params![chat_id], |row| row.get::<_, String>(0))?;
+        let mut entries = Vec::new();
+        for data in rows {
+            entries.push(serde_json::from_str(&data?)?);
+        }
+        Ok(entries)
+    }
+
+    fn append_decision(&self, entry: &DecisionEntity) -> Result<(), Box<dyn Error>> {
+        let connection = self.connection.lock().map_err(|e| e.to_string())?;
+        connection.execute(
+            "INSERT INTO decisions (chat_id, data) VALUES (?1, ?2)",
+            params![entry.chat_id, serde_json::to_string(entry)?],
+        )?;
+        Ok(())
+    }
+
+    fn load_decisions(&self, chat_id: &str) -> Result<Vec<DecisionEntity>, Box<dyn Error>> {
+        let connection = self.connection.lock().map_err(|e| e.to_string())?;
+        let mut stmt =
+            connection.prepare("SELECT data FROM decisions WHERE chat_id = ?1 ORDER BY id")?;
+        let rows = stmt.query_map(params![chat_id], |row| row.get::<_, String>(0))?;
+        let mut entries = Vec::new();
+        for data in rows {
+            entries.push(serde_json::from_str(&data?)?);
+        }
+        Ok(entries)
+    }
+}
@@ -0,0 +1,314 @@
+use std::{collections::HashMap, error::Error};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::adapter::{
+    migrate, AuditEntryEntity, BotMemoryEntity, ManagedChatEntity, UserEntity,
+    CURRENT_SCHEMA_VERSION,
+};
+
+const CSV_HEADER: [&str; 14] = [
+    "record",
+    "chat_id",
+    "user_id",
+    "username",
+    "firstname",
+    "last_activity_unix_time",
+    "created_unix_time",
+    "actor",
+    "target",
+    "action",
+    "parameters",
+    "trigger_message",
+    "result",
+    "profile",
+];
+const CHAT_RECORD: &str = "chat";
+const USER_RECORD: &str = "user";
+const ADMINISTRATOR_RECORD: &str = "administrator";
+const AUDIT_RECORD: &str = "audit";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn from_argument(argument: &str) -> Option<Self> {
+        match argument.to_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+
+    /// Detects the format of an uploaded document by its file name, falling back to its content.
+    pub fn detect(file_name: &str, content: &[u8]) -> Self {
+        let file_name = file_name.to_lowercase();
+        if file_name.ends_with(".csv") {
+            return Self::Csv;
+        }
+        if file_name.ends_with(".json") {
+            return Self::Json;
+        }
+        match content.iter().find(|byte| !byte.is_ascii_whitespace()) {
+            Some(b'{') => Self::Json,
+            _ => Self::Csv,
+        }
+    }
+
+    pub fn file_extension(&self) -> &str {
+        match self {
+            Self::Json => "json",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+/// Portable snapshot of managed chats and their audit log, exchanged by `/export` and `/import`.
+/// The JSON document holds the bot memory as stored, the CSV document one row per chat, user,
+/// administrator and audit entry. The complete profile of a user is kept as JSON column.
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct BotStateExport {
+    pub bot_memory: BotMemoryEntity,
+    #[serde(default)]
+    pub audit_log: Vec<AuditEntryEntity>,
+}
+
+impl BotStateExport {
+    pub fn encode(&self, format: ExportFormat) -> Result<Vec<u8>, Box<dyn Error>> {
+        match format {
+            ExportFormat::Json => Ok(serde_json::to_vec_pretty(self)?),
+            ExportFormat::Csv => Ok(self.to_csv()?.into_bytes()),
+        }
+    }
+
+    /// Parses and validates an exported document, bot memories of older versions are migrated.
+    pub fn decode(content: &[u8], format: ExportFormat) -> Result<Self, Box<dyn Error>> {
+        match format {
+            ExportFormat::Json => {
+                let mut document: Value = serde_json::from_slice(content)?;
+                let bot_memory = document
+                    .get_mut("bot_memory")
+                    .map(Value::take)
+                    .ok_or("Document contains no bot_memory")?;
+                let audit_log = match document.get_mut("audit_log") {
+                    Some(audit_log) => serde_json::from_value(audit_log.take())?,
+                    None => Vec::new(),
+                };
+                Ok(Self {
+                    bot_memory: serde_json::from_value(migrate(bot_memory)?)?,
+                    audit_log,
+                })
+            }
+            ExportFormat::Csv => Self::from_csv(std::str::from_utf8(content)?),
+        }
+    }
+
+    fn to_csv(&self) -> Result<String, Box<dyn Error>> {
+        let mut rows: Vec<Vec<String>> = vec![CSV_HEADER.iter().map(|c| c.to_string()).collect()];
+        let row = |record: &str, chat_id: &str, fields: &[(usize, String)]| {
+            let mut row = vec![String::new(); CSV_HEADER.len()];
+            row[0] = record.to_string();
+            row[1] = chat_id.to_string();
+            for (column, value) in fields {
+                row[*column] = value.clone();
+            }
+            row
+        };
+        for (chat_id, managed_chat) in &self.bot_memory.managed_chats {
+            rows.push(row(CHAT_RECORD, chat_id, &[]));
+            for user in managed_chat.user_map.values() {
+                rows.push(row(
+                    USER_RECORD,
+                    chat_id,
+                    &[
+                        (2, user.user_id.to_string()),
+                        (3, user.username.clone()),
+                        (4, user.firstname.clone()),
+                        (5, user.last_activity_unix_time.to_string()),
+                        (13, serde_json::to_string(user)?),
+                    ],
+                ));
+            }
+            for administrator in &managed_chat.administrators {
+                rows.push(row(
+                    ADMINISTRATOR_RECORD,
+                    chat_id,
+                    &[(2, administrator.to_string())],
+                ));
+            }
+        }
+        for entry in &self.audit_log {
+            rows.push(row(
+                AUDIT_RECORD,
+                &entry.chat_id,
+                &[
+                    (6, entry.created_unix_time.to_string()),
+                    (7, entry.actor.clone()),
+                    (8, entry.target.clone()),
+                    (9, entry.action.clone()),
+                    (10, serde_json::to_string(&entry.parameters)?),
+                    (11, entry.trigger_message.clone().unwrap_or_default()),
+                    (12, entry.result.clone()),
+                ],
+            ));
+        }
+        Ok(rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|field| escape_csv_field(field))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            })
+            .map(|line| line + "\r\n")
+            .collect())
+    }
+
+    fn from_csv(content: &str) -> Result<Self, Box<dyn Error>> {
+        let mut rows = parse_csv(content)?.into_iter();
+        if rows.next().as_deref() != Some(&CSV_HEADER.map(String::from)[..]) {
+            return Err("Unexpected CSV header".into());
+        }
+        let mut managed_chats: HashMap<String, ManagedChatEntity> = HashMap::new();
+        let mut audit_log = Vec::new();
+        for (line, row) in rows.enumerate() {
+            if row.len() != CSV_HEADER.len() {
+                return Err(format!("Row {} has {} columns", line + 2, row.len()).into());
+            }
+            let chat_id = row[1].clone();
+            match row[0].as_str() {
+                CHAT_RECORD => {
+                    managed_chats.entry(chat_id).or_default();
+                }
+                USER_RECORD => {
+                    let mut user: UserEntity = if row[13].is_empty() {
+                        UserEntity::default()
+                    } else {
+                        serde_json::from_str(&row[13])?
+                    };
+                    user.user_id = row[2].parse()?;
+                    user.username = row[3].clone();
+                    user.firstname = row[4].clone();
+                    user.last_activity_unix_time = row[5].parse()?;
+                    managed_chats
+                        .entry(chat_id)
+                        .or_default()
+                        .user_map
+                        .insert(user.user_id.to_string(), user);
+                }
+                ADMINISTRATOR_RECORD => managed_chats
+                    .entry(chat_id)
+                    .or_default()
+                    .administrators
+                    .push(row[2].parse()?),
+                AUDIT_RECORD => audit_log.push(AuditEntryEntity {
+                    created_unix_time: row[6].parse()?,
+                    chat_id,
+                    actor: row[7].clone(),
+                    target: row[8].clone(),
+                    action: row[9].clone(),
+                    parameters: serde_json::from_str(&row[10])?,
+                    trigger_message: Some(row[11].clone()).filter(|message| !message.is_empty()),
+                    result: row[12].clone(),
+                }),
+                other => return Err(format!("Unknown record {} in row {}", other, line + 2).into()),
+            }
+        }
+        Ok(Self {
+            bot_memory: BotMemoryEntity {
+                version: CURRENT_SCHEMA_VERSION,
+                managed_chats,
+            },
+            audit_log,
+        })
+    }
+}
+
+fn escape_csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Minimal RFC 4180 reader, quoted fields may contain separators, quotes and line breaks.
+fn parse_csv(content: &str) -> Result<Vec<Vec<String>>, Box<dyn Error>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut is_quoted = false;
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if is_quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if is_quoted => is_quoted = false,
+            '"' if field.is_empty() => is_quoted = true,
+            ',' if !is_quoted => row.push(std::mem::take(&mut field)),
+            '\r' if !is_quoted => {}
+            '\n' if !is_quoted => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            c => field.push(c),
+        }
+    }
+    if is_quoted {
+        return Err("Unterminated quoted CSV field".into());
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod state_export_test {
+    use serde_json::json;
+
+    use crate::adapter::{
+        AuditEntryEntity, BotMemoryEntity, BotStateExport, ExportFormat, UserEntity,
+    };
+
+    #[test]
+    fn should_restore_csv_export_successfully() {
+        let mut bot_memory = BotMemoryEntity::new();
+        let managed_chat = bot_memory
+            .managed_chats
+            .entry("-100".to_string())
+            .or_default();
+        managed_chat
+            .user_map
+            .insert("1".to_string(), UserEntity::new(1, "sabine", "Sabine", 10));
+        managed_chat.administrators.push(1);
+        let export = BotStateExport {
+            bot_memory,
+            audit_log: vec![AuditEntryEntity::new(
+                "-100",
+                "@admin",
+                "kevin",
+                "mute",
+                json!({ "mute_time_secs": 600 }),
+                Some("buy \"cheap\" coins,\nnow".to_string()),
+                "success",
+            )],
+        };
+
+        let content = export.encode(ExportFormat::Csv).unwrap();
+        let format = ExportFormat::detect("export.csv", &content);
+        let restored = BotStateExport::decode(&content, format).unwrap();
+
+        let managed_chat = restored.bot_memory.managed_chats.get("-100").unwrap();
+        assert_eq!(managed_chat.user_map.get("1").unwrap().firstname, "Sabine");
+        assert_eq!(managed_chat.administrators, vec![1]);
+        assert_eq!(restored.audit_log, export.audit_log);
+    }
+}
@@ -0,0 +1,91 @@
+use std::{env, error::Error, sync::Arc};
+
+use crate::adapter::{
+    AuditEntryEntity, BotMemoryEntity, DecisionEntity, HistoryEntryEntity, JsonFileStorage,
+    MemoryEntryEntity, SqliteStorage,
+};
+
+const DB_BACKEND_ENV: &str = "DB_BACKEND";
+
+/// Persistence backend behind `BotDatabase`.
+pub trait BotStorage: Send + Sync {
+    /// Loads the persisted bot memory, `None` if nothing has been stored yet.
+    fn load(&self) -> Result<Option<BotMemoryEntity>, Box<dyn Error>>;
+
+    /// Writes the complete bot memory.
+    fn save(&self, bot_memory: &BotMemoryEntity) -> Result<(), Box<dyn Error>>;
+
+    /// Writes the state of a single user of a managed chat. Backends which can't update
+    /// a single record fall back to writing the complete bot memory.
+    fn save_user(
+        &self,
+        bot_memory: &BotMemoryEntity,
+        _chat_id: &str,
+        _user_id: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        self.save(bot_memory)
+    }
+
+    /// Writes the state of several users at once, see `save_user`.
+    fn save_users(
+        &self,
+        bot_memory: &BotMemoryEntity,
+        _users: &[(String, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        self.save(bot_memory)
+    }
+
+    /// Loads the conversation history snapshot stored under `key`.
+    fn load_history(&self, key: &str) -> Result<Vec<HistoryEntryEntity>, Box<dyn Error>>;
+
+    /// Replaces the conversation history snapshot stored under `key`.
+    fn save_history(&self, key: &str, entries: &[HistoryEntryEntity])
+        -> Result<(), Box<dyn Error>>;
+
+    /// Appends an entry to the moderation audit log, entries are never changed afterwards.
+    fn append_audit(&self, entry: &AuditEntryEntity) -> Result<(), Box<dyn Error>>;
+
+    /// Loads the audit log of a chat, oldest entry first.
+    fn load_audit(&self, chat_id: &str) -> Result<Vec<AuditEntryEntity>, Box<dyn Error>>;
+
+    /// Appends an entry to the semantic memory.
+    fn append_memory(&self, entry: &MemoryEntryEntity) -> Result<(), Box<dyn Error>>;
+
+    /// Loads the semantic memory of a chat, oldest entry first.
+    fn load_memory(&self, chat_id: &str) -> Result<Vec<MemoryEntryEntity>, Box<dyn Error>>;
+
+    /// Appends a moderation decision to the decision log.
+    fn append_decision(&self, entry: &DecisionEntity) -> Result<(), Box<dyn Error>>;
+
+    /// Loads the decision log of a chat, oldest entry first.
+    fn load_decisions(&self, chat_id: &str) -> Result<Vec<DecisionEntity>, Box<dyn Error>>;
+}
+
+/// Opens the storage backend selected by the `DB_BACKEND` environment variable
+/// (`json` or `sqlite`, defaults to `json`).
+pub fn open_configured_storage() -> Arc<dyn BotStorage> {
+    let backend = env::var(DB_BACKEND_ENV).unwrap_or_else(|_| "json".to_string());
+    match backend.to_lowercase().as_str() {
+        "sqlite" => match SqliteStorage::from_env() {
+            Ok(storage) => Arc::new(storage),
+            Err(e) => panic!("Could not open sqlite database cause: {}", e),
+        },
+        "json" => Arc::new(JsonFileStorage::from_env()),
+        other => panic!("Unknown database backend: {}", other),
+    }
+}
+
+/// One-shot import of an existing `bot_db.json` file into the configured storage backend.
+pub fn import_json_database(json_file_path: &str) -> Result<usize, Box<dyn Error>> {
+    let source = JsonFileStorage::new(json_file_path);
+    let bot_memory = source
+        .load()?
+        .ok_or_else(|| format!("File {} does not exist", json_file_path))?;
+    let target = open_configured_storage();
+    target.save(&bot_memory)?;
+    Ok(bot_memory
+        .managed_chats
+        .values()
+        .map(|managed_chat| managed_chat.user_map.len())
+        .sum())
+}
@@ -0,0 +1,101 @@
+use std::env;
+
+use anyhow::anyhow;
+use reqwest::multipart::{Form, Part};
+use serde::Deserialize;
+
+const TELEGRAM_API_URL: &str = "https://api.telegram.org";
+
+#[derive(Deserialize)]
+struct TelegramResponse<T> {
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>,
+}
+
+impl<T> TelegramResponse<T> {
+    fn into_result(self) -> Result<Option<T>, anyhow::Error> {
+        if !self.ok {
+            return Err(anyhow!(
+                "Telegram request failed: {}",
+                self.description.unwrap_or_default()
+            ));
+        }
+        Ok(self.result)
+    }
+}
+
+#[derive(Deserialize)]
+struct TelegramFile {
+    file_path: Option<String>,
+}
+
+fn telegram_token() -> String {
+    env::var("TELEGRAM_TOKEN").expect("TELEGRAM_TOKEN environment variable not set")
+}
+
+/// mobot can't upload files, so documents are sent with the Bot API directly.
+pub async fn send_document(
+    chat_id: i64,
+    file_name: &str,
+    content: Vec<u8>,
+    caption: &str,
+) -> Result<(), anyhow::Error> {
+    let form = Form::new()
+        .text("chat_id", chat_id.to_string())
+        .text("caption", caption.to_string())
+        .part(
+            "document",
+            Part::bytes(content).file_name(file_name.to_string()),
+        );
+    let response: TelegramResponse<serde_json::Value> = reqwest::Client::new()
+        .post(format!(
+            "{}/bot{}/sendDocument",
+            TELEGRAM_API_URL,
+            telegram_token()
+        ))
+        .multipart(form)
+        .send()
+        .await?
+        .json()
+        .await?;
+    response.into_result()?;
+    Ok(())
+}
+
+/// Downloads a document which has been sent to the bot, `None` if it is larger than `max_size`
+/// bytes. The limit is checked while downloading, the size Telegram reports may be missing.
+pub async fn download_document(
+    file_id: &str,
+    max_size: u64,
+) -> Result<Option<Vec<u8>>, anyhow::Error> {
+    let token = telegram_token();
+    let client = reqwest::Client::new();
+    let response: TelegramResponse<TelegramFile> = client
+        .post(format!("{}/bot{}/getFile", TELEGRAM_API_URL, token))
+        .json(&serde_json::json!({ "file_id": file_id }))
+        .send()
+        .await?
+        .json()
+        .await?;
+    let file_path = response
+        .into_result()?
+        .and_then(|file| file.file_path)
+        .ok_or_else(|| anyhow!("Telegram returned no file path for {}", file_id))?;
+    let mut response = client
+        .get(format!(
+            "{}/file/bot{}/{}",
+            TELEGRAM_API_URL, token, file_path
+        ))
+        .send()
+        .await?
+        .error_for_status()?;
+    let mut content: Vec<u8> = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if (content.len() + chunk.len()) as u64 > max_size {
+            return Ok(None);
+        }
+        content.extend_from_slice(&chunk);
+    }
+    Ok(Some(content))
+}
@@ -0,0 +1,182 @@
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+use mobot::{
+    api::{EditMessageBase, EditMessageTextRequest, SendMessageRequest},
+    API,
+};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::application::{configured_locale, Locale, ReplyPart, Text};
+
+const STREAM_EDIT_INTERVAL_MILLIS_ENV: &str = "STREAM_EDIT_INTERVAL_MILLIS";
+// Telegram allows about 20 messages per minute in a group, edits included
+const DEFAULT_STREAM_EDIT_INTERVAL_MILLIS: u64 = 3000;
+const MAX_MESSAGE_LENGTH: usize = 4096;
+const PLACEHOLDER_TEXT: &str = "…";
+
+/// Streams an LLM answer into a Telegram message.
+///
+/// A placeholder message is sent right away and edited with the text received so far, at most
+/// once per edit interval. Once the answer is complete the message is edited a last time,
+/// answers exceeding the Telegram message length are continued in further messages.
+pub struct StreamingReply<'a> {
+    api: &'a API,
+    chat_id: i64,
+    message_thread_id: Option<i64>,
+    placeholder: bool,
+    locale: Locale,
+    edit_interval: Duration,
+    message_id: Option<i64>,
+    message_ids: Vec<i64>,
+    shown_text: String,
+}
+
+impl<'a> StreamingReply<'a> {
+    pub fn new(api: &'a API, chat_id: i64, message_thread_id: Option<i64>) -> Self {
+        Self {
+            api,
+            chat_id,
+            message_thread_id,
+            placeholder: true,
+            locale: configured_locale(),
+            edit_interval: Duration::from_millis(
+                env::var(STREAM_EDIT_INTERVAL_MILLIS_ENV)
+                    .ok()
+                    .and_then(|millis| millis.parse().ok())
+                    .unwrap_or(DEFAULT_STREAM_EDIT_INTERVAL_MILLIS),
+            ),
+            message_id: None,
+            message_ids: Vec::new(),
+            shown_text: String::new(),
+        }
+    }
+
+    /// Language of the text shown if no answer has been received.
+    pub fn with_locale(mut self, locale: Locale) -> Self {
+        self.locale = locale;
+        self
+    }
+
+    /// For answers which may turn out to be empty, e.g. the reply of a verdict. No placeholder
+    /// is sent then, the first message goes out with the first received text.
+    pub fn without_placeholder(mut self) -> Self {
+        self.placeholder = false;
+        self
+    }
+
+    /// Shows the text parts received from `receiver` until the sender is dropped and returns
+    /// the whole answer with the ids of the messages showing it. Discarded text is replaced by
+    /// the text following it.
+    pub async fn forward(
+        mut self,
+        mut receiver: UnboundedReceiver<ReplyPart>,
+    ) -> (String, Vec<i64>) {
+        let mut text = String::new();
+        if self.placeholder {
+            self.show(PLACEHOLDER_TEXT).await;
+        }
+        let mut last_edit = Instant::now();
+
+        while let Some(part) = receiver.recv().await {
+            match part {
+                ReplyPart::Text(part) => text.push_str(&part),
+                ReplyPart::Discard => text.clear(),
+            }
+            if last_edit.elapsed() < self.edit_interval {
+                continue;
+            }
+            if let Some(visible_text) = visible_text(&text) {
+                self.show(&truncate(&visible_text)).await;
+                last_edit = Instant::now();
+            }
+        }
+
+        match visible_text(&text) {
+            Some(visible_text) => self.show_final(&visible_text).await,
+            None if self.message_id.is_some() => self.show(Text::NoAnswer.get(self.locale)).await,
+            None => {}
+        }
+        (text, self.message_ids)
+    }
+
+    async fn show_final(&mut self, text: &str) {
+        let chunks = split_message(text);
+        let mut chunk_iter = chunks.iter();
+        if let Some(first_chunk) = chunk_iter.next() {
+            self.show(first_chunk).await;
+        }
+        for chunk in chunk_iter {
+            match self.api.send_message(&self.send_request(chunk)).await {
+                Ok(message) => self.message_ids.push(message.message_id),
+                Err(e) => warn!("Failed to send streamed answer cause: {}", e),
+            }
+        }
+    }
+
+    /// Sends the message on first use and edits it afterwards. Failed edits are only logged,
+    /// the next edit carries the whole text anyway.
+    async fn show(&mut self, text: &str) {
+        if text == self.shown_text {
+            return;
+        }
+        match self.message_id {
+            Some(message_id) => {
+                let edit_request = EditMessageTextRequest {
+                    base: EditMessageBase::new()
+                        .with_chat_id(self.chat_id)
+                        .with_message_id(message_id),
+                    text: text.to_string(),
+                };
+                if let Err(e) = self.api.edit_message_text(&edit_request).await {
+                    warn!("Failed to edit streamed answer cause: {}", e);
+                    return;
+                }
+            }
+            None => match self.api.send_message(&self.send_request(text)).await {
+                Ok(message) => {
+                    self.message_id = Some(message.message_id);
+                    self.message_ids.push(message.message_id);
+                }
+                Err(e) => {
+                    warn!("Failed to send streamed answer cause: {}", e);
+                    return;
+                }
+            },
+        }
+        self.shown_text = text.to_string();
+    }
+
+    fn send_request(&self, text: &str) -> SendMessageRequest {
+        match self.message_thread_id {
+            Some(message_thread_id) => SendMessageRequest::new(self.chat_id, text)
+                .with_message_thread_id(message_thread_id),
+            None => SendMessageRequest::new(self.chat_id, text),
+        }
+    }
+}
+
+/// Text to show, `None` while there is nothing to show.
+fn visible_text(text: &str) -> Option<String> {
+    let trimmed_text = text.trim();
+    if trimmed_text.is_empty() {
+        return None;
+    }
+    Some(trimmed_text.to_string())
+}
+
+fn truncate(text: &str) -> String {
+    text.chars().take(MAX_MESSAGE_LENGTH).collect()
+}
+
+/// Splits a text into chunks fitting into a single Telegram message.
+fn split_message(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    chars
+        .chunks(MAX_MESSAGE_LENGTH)
+        .map(|chunk| chunk.iter().collect())
+        .collect()
+}
@@ -0,0 +1,41 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static NEXT_DIRECTORY_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Empty directory of a single test, removed with everything in it when dropped.
+pub struct TestDirectory {
+    path: PathBuf,
+}
+
+impl TestDirectory {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "ferrisbot_{}_{}_{}",
+            name,
+            std::process::id(),
+            NEXT_DIRECTORY_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Path of the database file, the other storage files are placed next to it.
+    pub fn database_file(&self) -> String {
+        self.path.join("bot_db.json").to_string_lossy().to_string()
+    }
+}
+
+impl Drop for TestDirectory {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
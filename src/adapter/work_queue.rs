@@ -0,0 +1,192 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    env,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use futures::{future::BoxFuture, FutureExt};
+use log::{debug, error, warn};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+const LLM_QUEUE_CAPACITY_ENV: &str = "LLM_QUEUE_CAPACITY";
+const LLM_QUEUE_CONCURRENCY_ENV: &str = "LLM_QUEUE_CONCURRENCY";
+const LLM_QUEUE_MAX_AGE_SECS_ENV: &str = "LLM_QUEUE_MAX_AGE_SECS";
+const DEFAULT_QUEUE_CAPACITY: usize = 100;
+const DEFAULT_QUEUE_CONCURRENCY: usize = 2;
+const DEFAULT_QUEUE_MAX_AGE_SECS: u64 = 300;
+
+struct Job {
+    created_unix_time: u64,
+    task: BoxFuture<'static, ()>,
+    // Frees the slot in the queue once the job is done or dropped
+    _capacity_permit: OwnedSemaphorePermit,
+}
+
+#[derive(Default)]
+struct KeyQueue {
+    jobs: VecDeque<Job>,
+    is_running: bool,
+}
+
+/// Bounded queue of the LLM work. Jobs with the same key, a chat or forum topic, run one after
+/// another in the order they were enqueued, jobs of different keys run concurrently up to the
+/// concurrency limit. If the queue is full, `enqueue` waits until a job is done. Jobs whose
+/// message has become older than the maximum age by the time their turn comes are dropped.
+#[derive(Clone)]
+pub struct WorkQueue {
+    capacity: Arc<Semaphore>,
+    concurrency: Arc<Semaphore>,
+    max_age_secs: u64,
+    key_queues: Arc<Mutex<HashMap<String, KeyQueue>>>,
+}
+
+impl Default for WorkQueue {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_QUEUE_CAPACITY,
+            DEFAULT_QUEUE_CONCURRENCY,
+            DEFAULT_QUEUE_MAX_AGE_SECS,
+        )
+    }
+}
+
+impl WorkQueue {
+    pub fn new(capacity: usize, concurrency: usize, max_age_secs: u64) -> Self {
+        Self {
+            capacity: Arc::new(Semaphore::new(capacity.max(1))),
+            concurrency: Arc::new(Semaphore::new(concurrency.max(1))),
+            max_age_secs,
+            key_queues: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Queue configured by `LLM_QUEUE_CAPACITY`, `LLM_QUEUE_CONCURRENCY` and
+    /// `LLM_QUEUE_MAX_AGE_SECS`.
+    pub fn from_env() -> Self {
+        let env_or = |name: &str, default: u64| -> u64 {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        Self::new(
+            env_or(LLM_QUEUE_CAPACITY_ENV, DEFAULT_QUEUE_CAPACITY as u64) as usize,
+            env_or(LLM_QUEUE_CONCURRENCY_ENV, DEFAULT_QUEUE_CONCURRENCY as u64) as usize,
+            env_or(LLM_QUEUE_MAX_AGE_SECS_ENV, DEFAULT_QUEUE_MAX_AGE_SECS),
+        )
+    }
+
+    /// Adds a job for the message sent at `created_unix_time`, waits while the queue is full.
+    pub async fn enqueue(
+        &self,
+        key: &str,
+        created_unix_time: u64,
+        task: impl Future<Output = ()> + Send + 'static,
+    ) {
+        let capacity_permit: OwnedSemaphorePermit = self
+            .capacity
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("Work queue capacity is never closed");
+        let job = Job {
+            created_unix_time,
+            task: task.boxed(),
+            _capacity_permit: capacity_permit,
+        };
+        let is_worker_needed: bool = {
+            let mut key_queues = self.key_queues.lock().unwrap();
+            let key_queue: &mut KeyQueue = key_queues.entry(key.to_string()).or_default();
+            key_queue.jobs.push_back(job);
+            !std::mem::replace(&mut key_queue.is_running, true)
+        };
+        if is_worker_needed {
+            tokio::spawn(self.clone().run_jobs(key.to_string()));
+        }
+    }
+
+    /// Next job of a key, the key is removed when it has no more jobs.
+    fn next_job(&self, key: &str) -> Option<Job> {
+        let mut key_queues = self.key_queues.lock().unwrap();
+        let job_opt: Option<Job> = key_queues.get_mut(key)?.jobs.pop_front();
+        if job_opt.is_none() {
+            key_queues.remove(key);
+        }
+        job_opt
+    }
+
+    async fn run_jobs(self, key: String) {
+        while let Some(job) = self.next_job(&key) {
+            let _concurrency_permit = self
+                .concurrency
+                .acquire()
+                .await
+                .expect("Work queue concurrency is never closed");
+            let now: u64 = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            let age_secs: u64 = now.saturating_sub(job.created_unix_time);
+            if age_secs > self.max_age_secs {
+                warn!("Dropping job of {} which is {}s old", key, age_secs);
+                continue;
+            }
+            debug!("Running job of {}", key);
+            // A panicking job must not stop the jobs queued behind it
+            if let Err(e) = tokio::spawn(job.task).await {
+                error!("Job of {} failed cause: {}", key, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod work_queue_test {
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, SystemTime},
+    };
+
+    use tokio::sync::oneshot;
+
+    use crate::adapter::WorkQueue;
+
+    #[tokio::test]
+    async fn should_run_jobs_of_a_key_in_order_and_drop_stale_ones() {
+        let work_queue = WorkQueue::new(10, 2, 60);
+        let now: u64 = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let finished: Arc<Mutex<Vec<&str>>> = Arc::new(Mutex::new(Vec::new()));
+        let (done_sender, done_receiver) = oneshot::channel();
+
+        for (name, delay_millis) in [("first", 30), ("second", 0)] {
+            let finished = finished.clone();
+            work_queue
+                .enqueue("chat", now, async move {
+                    tokio::time::sleep(Duration::from_millis(delay_millis)).await;
+                    finished.lock().unwrap().push(name);
+                })
+                .await;
+        }
+        let stale_finished = finished.clone();
+        work_queue
+            .enqueue("chat", now - 120, async move {
+                stale_finished.lock().unwrap().push("stale");
+            })
+            .await;
+        let last_finished = finished.clone();
+        work_queue
+            .enqueue("chat", now, async move {
+                last_finished.lock().unwrap().push("last");
+                let _ = done_sender.send(());
+            })
+            .await;
+
+        done_receiver.await.unwrap();
+        assert_eq!(*finished.lock().unwrap(), vec!["first", "second", "last"]);
+    }
+}
@@ -0,0 +1,273 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    error::Error,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+
+use log::{debug, error, info};
+use tokio::sync::Notify;
+
+use crate::adapter::{flush_json_logs, BotMemoryEntity, BotStorage, HistoryEntryEntity};
+
+const FLUSH_INTERVAL_ENV: &str = "DB_FLUSH_INTERVAL_SECS";
+const FLUSH_MAX_CHANGES_ENV: &str = "DB_FLUSH_MAX_CHANGES";
+const DEFAULT_FLUSH_INTERVAL_SECS: u64 = 5;
+const DEFAULT_FLUSH_MAX_CHANGES: usize = 50;
+
+static FLUSHER: OnceLock<WriteBehindFlusher> = OnceLock::new();
+
+#[derive(Default)]
+struct PendingWrites {
+    is_full_save: bool,
+    dirty_users: HashSet<(String, String)>,
+    history: HashMap<String, Vec<HistoryEntryEntity>>,
+    change_count: usize,
+}
+
+struct WriteBehindState {
+    /// Latest state of the bot memory, ahead of the storage until the next flush.
+    bot_memory: BotMemoryEntity,
+    pending: PendingWrites,
+}
+
+/// Batches the writes of the bot memory and of the conversation history, a background task
+/// writes them to the storage every flush interval or as soon as enough changes are pending.
+/// Message handling only updates the in memory state and never waits for disk I/O.
+#[derive(Clone)]
+pub struct WriteBehindFlusher {
+    storage: Arc<dyn BotStorage>,
+    state: Arc<Mutex<WriteBehindState>>,
+    flush_lock: Arc<Mutex<()>>,
+    notify: Arc<Notify>,
+    max_changes: usize,
+}
+
+impl WriteBehindFlusher {
+    /// Returns the running flusher, started with `bot_memory` as its initial state.
+    /// Outside of a tokio runtime no flusher can run and `None` is returned.
+    pub fn get_or_start(
+        storage: &Arc<dyn BotStorage>,
+        bot_memory: &BotMemoryEntity,
+    ) -> Option<&'static WriteBehindFlusher> {
+        if let Some(flusher) = FLUSHER.get() {
+            return Some(flusher);
+        }
+        let runtime = tokio::runtime::Handle::try_current().ok()?;
+        Some(FLUSHER.get_or_init(|| {
+            let flusher = Self::new(storage.clone(), bot_memory.clone());
+            runtime.spawn(flusher.clone().run(flush_interval()));
+            flusher
+        }))
+    }
+
+    pub fn running() -> Option<&'static WriteBehindFlusher> {
+        FLUSHER.get()
+    }
+
+    fn new(storage: Arc<dyn BotStorage>, bot_memory: BotMemoryEntity) -> Self {
+        Self {
+            storage,
+            state: Arc::new(Mutex::new(WriteBehindState {
+                bot_memory,
+                pending: PendingWrites::default(),
+            })),
+            flush_lock: Arc::new(Mutex::new(())),
+            notify: Arc::new(Notify::new()),
+            max_changes: env::var(FLUSH_MAX_CHANGES_ENV)
+                .ok()
+                .and_then(|changes| changes.parse().ok())
+                .unwrap_or(DEFAULT_FLUSH_MAX_CHANGES),
+        }
+    }
+
+    async fn run(self, interval: Duration) {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = self.notify.notified() => {}
+            }
+            self.flush().await;
+        }
+    }
+
+    pub fn storage(&self) -> Arc<dyn BotStorage> {
+        self.storage.clone()
+    }
+
+    /// Latest bot memory including the changes which haven't been flushed yet.
+    pub fn snapshot(&self) -> BotMemoryEntity {
+        self.state.lock().unwrap().bot_memory.clone()
+    }
+
+    /// History snapshot of `key` which hasn't been flushed yet.
+    pub fn pending_history(&self, key: &str) -> Option<Vec<HistoryEntryEntity>> {
+        self.state.lock().unwrap().pending.history.get(key).cloned()
+    }
+
+    pub fn save(&self, bot_memory: &BotMemoryEntity) {
+        self.update(|state| {
+            state.bot_memory = bot_memory.clone();
+            state.pending.is_full_save = true;
+        });
+    }
+
+    pub fn save_user(&self, bot_memory: &BotMemoryEntity, chat_id: &str, user_id: &str) {
+        self.update(|state| {
+            let user_opt = bot_memory
+                .managed_chats
+                .get(chat_id)
+                .and_then(|managed_chat| managed_chat.user_map.get(user_id));
+            match state.bot_memory.managed_chats.get_mut(chat_id) {
+                Some(managed_chat) => {
+                    match user_opt {
+                        Some(user) => managed_chat
+                            .user_map
+                            .insert(user_id.to_string(), user.clone()),
+                        None => managed_chat.user_map.remove(user_id),
+                    };
+                    state
+                        .pending
+                        .dirty_users
+                        .insert((chat_id.to_string(), user_id.to_string()));
+                }
+                None => {
+                    state.bot_memory = bot_memory.clone();
+                    state.pending.is_full_save = true;
+                }
+            }
+        });
+    }
+
+    pub fn save_history(&self, key: &str, entries: Vec<HistoryEntryEntity>) {
+        self.update(|state| {
+            state.pending.history.insert(key.to_string(), entries);
+        });
+    }
+
+    fn update(&self, change: impl FnOnce(&mut WriteBehindState)) {
+        let change_count = {
+            let mut state = self.state.lock().unwrap();
+            change(&mut state);
+            state.pending.change_count += 1;
+            state.pending.change_count
+        };
+        if change_count >= self.max_changes {
+            self.notify.notify_one();
+        }
+    }
+
+    /// Writes all pending changes to the storage.
+    pub async fn flush(&self) {
+        let flusher = self.clone();
+        if let Err(e) = tokio::task::spawn_blocking(move || flusher.flush_blocking()).await {
+            error!("Flush task failed cause: {}", e);
+        }
+    }
+
+    fn flush_blocking(&self) {
+        let _flush_guard = self.flush_lock.lock().unwrap();
+        let (bot_memory, pending) = {
+            let mut state = self.state.lock().unwrap();
+            if state.pending.change_count == 0 {
+                return;
+            }
+            let pending = std::mem::take(&mut state.pending);
+            (state.bot_memory.clone(), pending)
+        };
+        debug!("Flushing {} pending changes", pending.change_count);
+
+        if let Err(e) = self.write_memory(&bot_memory, &pending) {
+            error!(
+                "Could not flush bot memory, retrying with the next flush cause: {}",
+                e
+            );
+            let mut state = self.state.lock().unwrap();
+            state.pending.is_full_save = true;
+            state.pending.change_count += 1;
+        }
+        for (key, entries) in pending.history {
+            if let Err(e) = self.storage.save_history(&key, &entries) {
+                error!("Could not flush history {} cause: {}", key, e);
+                let mut state = self.state.lock().unwrap();
+                state.pending.history.entry(key).or_insert(entries);
+                state.pending.change_count += 1;
+            }
+        }
+    }
+
+    fn write_memory(
+        &self,
+        bot_memory: &BotMemoryEntity,
+        pending: &PendingWrites,
+    ) -> Result<(), Box<dyn Error>> {
+        if pending.is_full_save {
+            return self.storage.save(bot_memory);
+        }
+        if pending.dirty_users.is_empty() {
+            return Ok(());
+        }
+        let users: Vec<(String, String)> = pending.dirty_users.iter().cloned().collect();
+        self.storage.save_users(bot_memory, &users)
+    }
+}
+
+fn flush_interval() -> Duration {
+    Duration::from_secs(
+        env::var(FLUSH_INTERVAL_ENV)
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(DEFAULT_FLUSH_INTERVAL_SECS),
+    )
+}
+
+/// Final flush of all pending changes, must be awaited before the process exits.
+pub async fn flush_pending_writes() {
+    if let Some(flusher) = FLUSHER.get() {
+        info!("Flushing pending writes before shutdown");
+        flusher.flush().await;
+    }
+    if let Err(e) = tokio::task::spawn_blocking(flush_json_logs).await {
+        error!("Could not flush the append-only logs cause: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod write_behind_test {
+    use std::sync::Arc;
+
+    use crate::adapter::{
+        test_directory::TestDirectory, write_behind::WriteBehindFlusher, BotMemoryEntity,
+        BotStorage, JsonFileStorage, UserEntity,
+    };
+
+    const CHAT_ID: &str = "-100";
+
+    #[test]
+    fn should_write_pending_changes_on_flush() {
+        let directory = TestDirectory::new("write_behind");
+        let storage: Arc<dyn BotStorage> =
+            Arc::new(JsonFileStorage::new(&directory.database_file()));
+        let mut bot_memory = BotMemoryEntity::new();
+        bot_memory
+            .managed_chats
+            .insert(CHAT_ID.to_string(), Default::default());
+        let flusher = WriteBehindFlusher::new(storage.clone(), bot_memory.clone());
+
+        flusher.save(&bot_memory);
+        bot_memory
+            .managed_chats
+            .get_mut(CHAT_ID)
+            .unwrap()
+            .user_map
+            .insert("1".to_string(), UserEntity::new(1, "sabine", "Sabine", 1));
+        flusher.save_user(&bot_memory, CHAT_ID, "1");
+        assert!(storage.load().unwrap().is_none());
+
+        flusher.flush_blocking();
+
+        let stored = storage.load().unwrap().unwrap();
+        assert!(stored.managed_chats[CHAT_ID].user_map.contains_key("1"));
+    }
+}
@@ -0,0 +1,121 @@
+use std::env;
+
+use log::warn;
+use regex::Regex;
+
+use crate::application::{ModerationVerdict, VerdictAction, VerdictSeverity};
+
+const DEGRADED_BLOCKED_PATTERN_ENV: &str = "DEGRADED_BLOCKED_PATTERN";
+const DEGRADED_MIN_MESSAGES_FOR_LINKS_ENV: &str = "DEGRADED_MIN_MESSAGES_FOR_LINKS";
+const DEFAULT_MIN_MESSAGES_FOR_LINKS: u64 = 5;
+const LINK_PATTERN: &str = r"(?i)\b(https?://|www\.|t\.me/)\S+";
+
+/// Deterministic moderation while no LLM backend is available. Messages matching the blocked
+/// pattern and links of members with only a few messages are escalated to the admins, nothing
+/// is sanctioned without an admin looking at it.
+#[derive(Clone, Debug)]
+pub struct DegradedModeFilter {
+    blocked_pattern: Option<Regex>,
+    link_pattern: Regex,
+    min_messages_for_links: u64,
+}
+
+impl Default for DegradedModeFilter {
+    fn default() -> Self {
+        Self::new(None, DEFAULT_MIN_MESSAGES_FOR_LINKS)
+    }
+}
+
+impl DegradedModeFilter {
+    pub fn new(blocked_pattern: Option<Regex>, min_messages_for_links: u64) -> Self {
+        Self {
+            blocked_pattern,
+            link_pattern: Regex::new(LINK_PATTERN).unwrap(),
+            min_messages_for_links,
+        }
+    }
+
+    /// Filter configured by `DEGRADED_BLOCKED_PATTERN`, a case-insensitive regular expression,
+    /// and `DEGRADED_MIN_MESSAGES_FOR_LINKS`.
+    pub fn from_env() -> Self {
+        let blocked_pattern: Option<Regex> = env::var(DEGRADED_BLOCKED_PATTERN_ENV)
+            .ok()
+            .filter(|pattern| !pattern.trim().is_empty())
+            .and_then(|pattern| match Regex::new(&format!("(?i){}", pattern)) {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    warn!(
+                        "Ignoring invalid {} cause: {}",
+                        DEGRADED_BLOCKED_PATTERN_ENV, e
+                    );
+                    None
+                }
+            });
+        Self::new(
+            blocked_pattern,
+            env::var(DEGRADED_MIN_MESSAGES_FOR_LINKS_ENV)
+                .ok()
+                .and_then(|count| count.parse().ok())
+                .unwrap_or(DEFAULT_MIN_MESSAGES_FOR_LINKS),
+        )
+    }
+
+    /// Escalation verdict for a message of a regular member, `None` if the message passes.
+    /// The message count of the author includes the checked message.
+    pub fn check(&self, message: &str, author_message_count: u64) -> Option<ModerationVerdict> {
+        let blocked_match_opt = self
+            .blocked_pattern
+            .as_ref()
+            .and_then(|blocked_pattern| blocked_pattern.find(message));
+        let reason: String = if let Some(blocked_match) = blocked_match_opt {
+            format!(
+                "The message contains the blocked expression \"{}\".",
+                blocked_match.as_str()
+            )
+        } else if author_message_count < self.min_messages_for_links
+            && self.link_pattern.is_match(message)
+        {
+            format!(
+                "The author posted a link with only {} messages in the chat.",
+                author_message_count
+            )
+        } else {
+            return None;
+        };
+        Some(ModerationVerdict {
+            action: VerdictAction::Escalate,
+            violated_rule: None,
+            severity: VerdictSeverity::Medium,
+            confidence: 1.0,
+            reply: String::new(),
+            reasoning: Some(format!(
+                "{} The LLM was unavailable, the message has been checked by the deterministic filters.",
+                reason
+            )),
+        })
+    }
+}
+
+#[cfg(test)]
+mod degraded_filter_test {
+    use regex::Regex;
+
+    use crate::application::degraded_filter::DegradedModeFilter;
+    use crate::application::VerdictAction;
+
+    #[test]
+    fn should_escalate_blocked_words_and_links_of_newcomers() {
+        let filter = DegradedModeFilter::new(Some(Regex::new(r"(?i)\bidiot\b").unwrap()), 5);
+
+        let insult = filter.check("Du IDIOT", 100).unwrap();
+        assert_eq!(insult.effective_action(), VerdictAction::Escalate);
+        assert!(insult.reasoning.unwrap().contains("IDIOT"));
+        assert!(filter
+            .check("Super Angebot: https://spam.example", 1)
+            .is_some());
+        assert!(filter
+            .check("Die Doku: https://doc.rust-lang.org", 40)
+            .is_none());
+        assert!(filter.check("Guten Morgen zusammen", 1).is_none());
+    }
+}
@@ -0,0 +1,207 @@
+use std::env;
+
+const BOT_LANGUAGE_ENV: &str = "BOT_LANGUAGE";
+
+/// Language of the texts the bot sends, chats choose theirs with the persona language.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Locale {
+    #[default]
+    De,
+    En,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::De, Locale::En];
+
+    /// Accepts language codes and the English or native language names.
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code.trim().to_lowercase().as_str() {
+            "de" | "german" | "deutsch" => Some(Locale::De),
+            "en" | "english" | "englisch" => Some(Locale::En),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Locale::De => "de",
+            Locale::En => "en",
+        }
+    }
+
+    /// Name of the language in prompts.
+    pub fn language_name(&self) -> &'static str {
+        match self {
+            Locale::De => "German",
+            Locale::En => "English",
+        }
+    }
+}
+
+/// Locale of chats without their own language, set by `BOT_LANGUAGE` (defaults to `de`).
+pub fn configured_locale() -> Locale {
+    env::var(BOT_LANGUAGE_ENV)
+        .ok()
+        .and_then(|code| Locale::from_code(&code))
+        .unwrap_or_default()
+}
+
+/// User-facing texts of the bot. Placeholders in braces are filled by `Text::format`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Text {
+    GreetingCommand,
+    SummaryCommand,
+    PrivateChatOnly,
+    InvalidMuteTime,
+    MuteFailed,
+    Muted,
+    UnmuteFailed,
+    Unmuted,
+    InactiveUser,
+    NoModerationActions,
+    ModlogEntry,
+    ModlogTriggerMessage,
+    UnsupportedExportFormat,
+    ExportCaption,
+    ImportUsage,
+    ImportTooLarge,
+    ImportInvalid,
+    Imported,
+    ImportSkippedChats,
+    ImportRejectedPersonas,
+    RememberUsage,
+    Remembered,
+    RememberFailed,
+    PersonaUsage,
+    PersonaUpdated,
+    PersonaRejected,
+    PromptReloaded,
+    PromptRejected,
+    Escalation,
+    WhyUsage,
+    WhyUnknown,
+    WhyDecision,
+    WhyReasoning,
+    WhyNoReasoning,
+    LlmUnavailable,
+    NoAnswer,
+}
+
+impl Text {
+    pub fn get(self, locale: Locale) -> &'static str {
+        match locale {
+            Locale::De => german(self),
+            Locale::En => english(self),
+        }
+    }
+
+    /// Localized text with its placeholders replaced, e.g. `("user", "@kevin")` for `{user}`.
+    pub fn format(self, locale: Locale, arguments: &[(&str, &str)]) -> String {
+        arguments
+            .iter()
+            .fold(self.get(locale).to_string(), |text, (name, value)| {
+                text.replace(&format!("{{{}}}", name), value)
+            })
+    }
+}
+
+fn german(text: Text) -> &'static str {
+    match text {
+        Text::GreetingCommand => "Begrüß die Gruppe",
+        Text::SummaryCommand => "Gib eine Zusammenfassung der letzten Chatverlauf",
+        Text::PrivateChatOnly => "Bitte sende diesen Befehl in einem privaten Chat mit mir, er enthält Mitgliederdaten.",
+        Text::InvalidMuteTime => "Keine gültige Zeitangabe zum Stummschalten gefunden. Unterstützt wird: /mute 10m, 2h, 1d",
+        Text::MuteFailed => "Stummschalten fehlgeschlagen",
+        Text::Muted => "@{user} Du bist jetzt stummgeschaltet!",
+        Text::UnmuteFailed => "Aufheben der Stummschaltung fehlgeschlagen",
+        Text::Unmuted => "@{user} Du bist nicht mehr stummgeschaltet!",
+        Text::InactiveUser => "{user} ist seit {months} Monaten im Chat {chat} inaktiv",
+        Text::NoModerationActions => "Keine Moderationsaktionen gefunden",
+        Text::ModlogEntry => "[{chat}] vor {age}: {actor} {action} {target} -> {result}",
+        Text::ModlogTriggerMessage => " (Nachricht: \"{message}\")",
+        Text::UnsupportedExportFormat => "Unterstützte Formate: /export json, /export csv",
+        Text::ExportCaption => "Export von {count} Chats, antworte mit /import auf dieses Dokument, um ihn wiederherzustellen",
+        Text::ImportUsage => "Antworte mit /import auf ein JSON- oder CSV-Dokument, das mit /export erstellt wurde",
+        Text::ImportTooLarge => "Das Dokument ist zu groß für den Import",
+        Text::ImportInvalid => "Das Dokument ist ungültig: {error}",
+        Text::Imported => "{users} Mitglieder importiert, das Audit-Log wird nicht importiert",
+        Text::ImportSkippedChats => ", übersprungene Chats, die du nicht administrierst: {chats}",
+        Text::ImportRejectedPersonas => ", abgelehnte Personas: {personas}",
+        Text::RememberUsage => "Bitte gib an, was ich mir merken soll: /remember <Fakt>",
+        Text::Remembered => "Notiert, das merke ich mir.",
+        Text::RememberFailed => "Merken fehlgeschlagen: {error}",
+        Text::PersonaUsage => "Ändere eine Einstellung mit /persona <{fields}> <Wert>",
+        Text::PersonaUpdated => "Die Persona-Einstellung {field} wurde aktualisiert.",
+        Text::PersonaRejected => "Die Persona-Einstellung wurde abgelehnt: {error}",
+        Text::PromptReloaded => "Die Prompt-Vorlage wurde neu geladen.",
+        Text::PromptRejected => "Die Prompt-Vorlage wurde abgelehnt: {error}",
+        Text::Escalation => "Bitte schau dir {chat} an: {user} schrieb \"{message}\" (Regel {rule}, Schwere {severity})",
+        Text::WhyUsage => "Antworte mit /why auf eine meiner Nachrichten, um zu sehen, warum ich sie geschrieben habe",
+        Text::WhyUnknown => "Zu dieser Nachricht ist keine Entscheidung gespeichert",
+        Text::WhyDecision => "Entscheidung: {action} (Regel {rule}, Schwere {severity}, Sicherheit {confidence})",
+        Text::WhyReasoning => "Überlegungen des Modells:\n{reasoning}",
+        Text::WhyNoReasoning => "Keine Überlegungen gespeichert, das Nachdenken war für diese Entscheidung ausgeschaltet.",
+        Text::LlmUnavailable => "Ich kann gerade nicht antworten, die Moderation läuft eingeschränkt weiter.",
+        Text::NoAnswer => "Leider ist mir keine Antwort eingefallen.",
+    }
+}
+
+fn english(text: Text) -> &'static str {
+    match text {
+        Text::GreetingCommand => "Greet the group",
+        Text::SummaryCommand => "Summarize the recent conversation",
+        Text::PrivateChatOnly => "Please send this command in a private chat with me, it contains member data.",
+        Text::InvalidMuteTime => "No valid time parameter found to mute user. Following format is supported: /mute 10m, 2h, 1d",
+        Text::MuteFailed => "Failed to mute user",
+        Text::Muted => "@{user} You are muted now!",
+        Text::UnmuteFailed => "Failed to unmute user",
+        Text::Unmuted => "@{user} You are unmuted now!",
+        Text::InactiveUser => "User {user} is inactive last {months} months in chat {chat}",
+        Text::NoModerationActions => "No moderation actions found",
+        Text::ModlogEntry => "[{chat}] {age} ago: {actor} {action} {target} -> {result}",
+        Text::ModlogTriggerMessage => " (message: \"{message}\")",
+        Text::UnsupportedExportFormat => "Supported formats: /export json, /export csv",
+        Text::ExportCaption => "Export of {count} chats, reply with /import to this document to restore it",
+        Text::ImportUsage => "Reply with /import to a JSON or CSV document created by /export",
+        Text::ImportTooLarge => "The document is too large to import",
+        Text::ImportInvalid => "The document is not valid: {error}",
+        Text::Imported => "Imported {users} users, the audit log is never imported",
+        Text::ImportSkippedChats => ", skipped chats you don't administrate: {chats}",
+        Text::ImportRejectedPersonas => ", rejected personas: {personas}",
+        Text::RememberUsage => "Please add the fact to remember: /remember <fact>",
+        Text::Remembered => "Noted, I'll remember that.",
+        Text::RememberFailed => "Failed to remember the fact: {error}",
+        Text::PersonaUsage => "Change a setting with /persona <{fields}> <value>",
+        Text::PersonaUpdated => "The persona setting {field} has been updated.",
+        Text::PersonaRejected => "The persona setting has been rejected: {error}",
+        Text::PromptReloaded => "The prompt template has been reloaded.",
+        Text::PromptRejected => "The prompt template has been rejected: {error}",
+        Text::Escalation => "Please have a look at {chat}: {user} wrote \"{message}\" (rule {rule}, severity {severity})",
+        Text::WhyUsage => "Reply with /why to one of my messages to see why I wrote it",
+        Text::WhyUnknown => "No decision has been recorded for this message",
+        Text::WhyDecision => "Decision: {action} (rule {rule}, severity {severity}, confidence {confidence})",
+        Text::WhyReasoning => "Reasoning of the model:\n{reasoning}",
+        Text::WhyNoReasoning => "No reasoning recorded, thinking was disabled for this decision.",
+        Text::LlmUnavailable => "I can't answer right now, moderation continues in a limited mode.",
+        Text::NoAnswer => "Sorry, I couldn't come up with an answer.",
+    }
+}
+
+#[cfg(test)]
+mod localization_test {
+    use crate::application::localization::{Locale, Text};
+
+    #[test]
+    fn should_fill_placeholders_of_every_locale() {
+        for locale in Locale::ALL {
+            let text = Text::InactiveUser.format(
+                locale,
+                &[("user", "kevin"), ("months", "2"), ("chat", "rust")],
+            );
+            assert!(text.contains("kevin") && text.contains('2') && text.contains("rust"));
+            assert!(!text.contains('{'));
+        }
+        assert_eq!(Locale::from_code("English"), Some(Locale::En));
+        assert_eq!(Locale::from_code("fr"), None);
+    }
+}
@@ -1,151 +1,415 @@
-use std::time::{Duration, SystemTime};
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
 
-use log::debug;
+use log::{debug, info};
 
-use crate::adapter::{BotDatabase, UserEntity};
+use crate::adapter::{
+    BotDatabase, BotMemoryEntity, ManagedChatEntity, NameChangeEntity, PersonaEntity,
+    SanctionEntity, UserEntity, SANCTION_KINDS, UNASSIGNED_CHAT_ID,
+};
 
+const MAX_NAME_HISTORY_SIZE: usize = 20;
+
+/// Members of the managed chats.
 #[derive(Clone, Default)]
 pub struct UserManagement {
-    pub bot_db: BotDatabase,
+    bot_db: Arc<RwLock<BotDatabase>>,
 }
 
 impl UserManagement {
     pub fn new() -> Self {
         let bot_db = BotDatabase::try_init();
-        Self { bot_db }
+        Self {
+            bot_db: Arc::new(RwLock::new(bot_db)),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_database(bot_db: BotDatabase) -> Self {
+        Self {
+            bot_db: Arc::new(RwLock::new(bot_db)),
+        }
+    }
+
+    fn read_chat<T>(&self, chat_id: &str, read: impl FnOnce(&ManagedChatEntity) -> T) -> Option<T> {
+        self.bot_db
+            .read()
+            .unwrap()
+            .bot_memory
+            .managed_chats
+            .get(chat_id)
+            .map(read)
+    }
+
+    fn write_chat<T>(
+        &self,
+        chat_id: &str,
+        write: impl FnOnce(&mut ManagedChatEntity) -> T,
+    ) -> Option<T> {
+        self.bot_db
+            .write()
+            .unwrap()
+            .bot_memory
+            .managed_chats
+            .get_mut(chat_id)
+            .map(write)
+    }
+
+    /// Registers a chat to be managed by the bot, chats which are already managed keep their state.
+    /// A new chat adopts the users tracked before any chat was registered.
+    pub fn register_managed_chat(&self, chat_id: &str) {
+        debug!("Registering managed chat: {}", chat_id);
+        if self.adopt_unassigned_chat(chat_id) {
+            return;
+        }
+        self.bot_db
+            .write()
+            .unwrap()
+            .bot_memory
+            .managed_chats
+            .entry(chat_id.to_string())
+            .or_default();
+    }
+
+    /// Moves the users and administrators tracked before any chat was registered to a chat
+    /// which isn't managed yet, returns whether there was anything to adopt.
+    pub fn adopt_unassigned_chat(&self, chat_id: &str) -> bool {
+        {
+            let mut bot_db = self.bot_db.write().unwrap();
+            let managed_chats = &mut bot_db.bot_memory.managed_chats;
+            if managed_chats.contains_key(chat_id) {
+                return false;
+            }
+            let Some(unassigned_chat) = managed_chats.remove(UNASSIGNED_CHAT_ID) else {
+                return false;
+            };
+            info!("Chat {} adopts the users tracked without chat", chat_id);
+            managed_chats.insert(chat_id.to_string(), unassigned_chat);
+        }
+        self.persist();
+        true
+    }
+
+    pub fn is_managed_chat(&self, chat_id: &str) -> bool {
+        self.read_chat(chat_id, |_| ()).is_some()
+    }
+
+    pub fn get_managed_chat_ids(&self) -> Vec<String> {
+        self.bot_db
+            .read()
+            .unwrap()
+            .bot_memory
+            .managed_chats
+            .keys()
+            .cloned()
+            .collect()
     }
 
     pub fn add_user(
-        &mut self,
+        &self,
+        chat_id: &str,
         user_id: i64,
         username: &str,
         firstname: &str,
         last_activity_unix_time: u64,
     ) {
         let user_entity = UserEntity::new(user_id, username, firstname, last_activity_unix_time);
-        self.bot_db
-            .bot_memory
-            .user_map
-            .insert(user_id.to_string(), user_entity);
+        self.write_chat(chat_id, |managed_chat| {
+            managed_chat
+                .user_map
+                .insert(user_id.to_string(), user_entity);
+        });
+    }
+
+    pub fn get_user(&self, chat_id: &str, user_id: i64) -> Option<UserEntity> {
+        self.read_chat(chat_id, |managed_chat| {
+            managed_chat.user_map.get(&user_id.to_string()).cloned()
+        })?
     }
 
-    pub fn get_inactive_users_since(&self, duration: Duration) -> Vec<UserEntity> {
+    pub fn get_inactive_users_since(&self, chat_id: &str, duration: Duration) -> Vec<UserEntity> {
         let current_time: u64 = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
             .unwrap()
             .as_secs();
-        self.bot_db
-            .bot_memory
-            .user_map
-            .values()
-            .filter(|user| current_time - user.last_activity_unix_time > duration.as_secs())
-            .cloned()
-            .collect()
+        self.read_chat(chat_id, |managed_chat| {
+            managed_chat
+                .user_map
+                .values()
+                .filter(|user| {
+                    current_time.saturating_sub(user.last_activity_unix_time) > duration.as_secs()
+                })
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default()
+    }
+
+    pub fn remove_user(&self, chat_id: &str, user_id: i64) {
+        self.write_chat(chat_id, |managed_chat| {
+            managed_chat.user_map.remove(&user_id.to_string());
+        });
     }
 
-    pub fn remove_user(&mut self, user_id: i64) {
-        self.bot_db.bot_memory.user_map.remove(&user_id.to_string());
+    /// Telegram names a forum topic only in the messages answering its creation.
+    pub fn set_topic_name(&self, chat_id: &str, message_thread_id: i64, name: &str) {
+        let is_changed: bool = self
+            .write_chat(chat_id, |managed_chat| {
+                managed_chat
+                    .topic_names
+                    .insert(message_thread_id, name.to_string())
+                    .is_none_or(|previous_name| previous_name != name)
+            })
+            .unwrap_or(false);
+        if is_changed {
+            self.persist();
+        }
     }
 
+    pub fn get_topic_name(&self, chat_id: &str, message_thread_id: i64) -> Option<String> {
+        self.read_chat(chat_id, |managed_chat| {
+            managed_chat.topic_names.get(&message_thread_id).cloned()
+        })
+        .flatten()
+    }
+
+    /// Tracks a message of a member, counts it and records changes of the member's names.
     pub fn update_user_activity(
-        &mut self,
+        &self,
+        chat_id: &str,
+        topic: &str,
         username: &str,
         firstname: &str,
         user_id: i64,
         last_activity_unix_time: u64,
     ) {
-        if let Some(user_entity) = self
-            .bot_db
-            .bot_memory
-            .user_map
-            .get_mut(&user_id.to_string())
-        {
-            user_entity.last_activity_unix_time = last_activity_unix_time;
+        let is_rekeyed_opt: Option<bool> = self.write_chat(chat_id, |managed_chat| {
+            let user_key = user_id.to_string();
+            let mut is_rekeyed = false;
+            if !managed_chat.user_map.contains_key(&user_key) {
+                // Users registered by /init are keyed by a placeholder until their first message
+                let placeholder_key_opt = managed_chat
+                    .user_map
+                    .iter()
+                    .find(|(_, user)| user.username == username)
+                    .map(|(key, _)| key.clone());
+                let user_entity = match placeholder_key_opt {
+                    Some(placeholder_key) => {
+                        is_rekeyed = true;
+                        managed_chat.user_map.remove(&placeholder_key).unwrap()
+                    }
+                    None => UserEntity::new(user_id, username, firstname, last_activity_unix_time),
+                };
+                managed_chat.user_map.insert(user_key.clone(), user_entity);
+            }
+
+            let user_entity = managed_chat.user_map.get_mut(&user_key).unwrap();
+            let is_username_changed =
+                !user_entity.username.is_empty() && user_entity.username != username;
+            let is_firstname_changed =
+                !user_entity.firstname.is_empty() && user_entity.firstname != firstname;
+            if is_username_changed || is_firstname_changed {
+                user_entity.name_history.push(NameChangeEntity {
+                    changed_unix_time: last_activity_unix_time,
+                    username: user_entity.username.clone(),
+                    firstname: user_entity.firstname.clone(),
+                });
+                if user_entity.name_history.len() > MAX_NAME_HISTORY_SIZE {
+                    user_entity.name_history.remove(0);
+                }
+            }
+            user_entity.user_id = user_id;
             user_entity.username = username.to_string();
             user_entity.firstname = firstname.to_string();
-            user_entity.user_id = user_id;
-        } else if let Some((index, _)) = self.get_user_by_name(username) {
-            self.remove_user(index.parse().unwrap());
-            self.add_user(user_id, username, firstname, last_activity_unix_time);
-        } else {
-            self.add_user(user_id, username, firstname, last_activity_unix_time);
+            user_entity.last_activity_unix_time = last_activity_unix_time;
+            user_entity.message_count += 1;
+            *user_entity
+                .topic_message_counts
+                .entry(topic.to_string())
+                .or_default() += 1;
+            debug!("User of chat {} {:?}", chat_id, user_entity);
+            is_rekeyed
+        });
+
+        match is_rekeyed_opt {
+            None => debug!("Chat {} is not managed, activity is not tracked", chat_id),
+            Some(true) => self.persist(),
+            Some(false) => self.persist_user(chat_id, user_id),
         }
-        self.persist();
-        debug!("Usermap {:?}", self.bot_db.bot_memory.user_map.values());
     }
 
-    pub fn contains_username(&self, username: &str) -> bool {
-        self.bot_db
-            .bot_memory
-            .user_map
-            .iter()
-            .any(|(_, user)| user.username == username)
+    /// Records a warning, mute or kick of a member, returns how many sanctions of that kind
+    /// the member has received or `None` if the member is unknown.
+    pub fn record_sanction(
+        &self,
+        chat_id: &str,
+        user_id: i64,
+        sanction: SanctionEntity,
+    ) -> Option<usize> {
+        let count: usize = self.write_chat(chat_id, |managed_chat| {
+            let user_entity = managed_chat.user_map.get_mut(&user_id.to_string())?;
+            let kind = sanction.kind.clone();
+            user_entity.sanctions.push(sanction);
+            Some(user_entity.count_sanctions(&kind))
+        })??;
+        self.persist_user(chat_id, user_id);
+        Some(count)
     }
 
-    pub fn get_user_by_name(&self, username: &str) -> Option<(&String, &UserEntity)> {
-        self.bot_db
-            .bot_memory
-            .user_map
-            .iter()
-            .find_map(|(index, user)| {
+    pub fn contains_username(&self, chat_id: &str, username: &str) -> bool {
+        self.read_chat(chat_id, |managed_chat| {
+            managed_chat
+                .user_map
+                .iter()
+                .any(|(_, user)| user.username == username)
+        })
+        .unwrap_or(false)
+    }
+
+    pub fn get_user_by_name(&self, chat_id: &str, username: &str) -> Option<(String, UserEntity)> {
+        self.read_chat(chat_id, |managed_chat| {
+            managed_chat.user_map.iter().find_map(|(index, user)| {
                 if user.username == username {
-                    Some((index, user))
+                    Some((index.clone(), user.clone()))
                 } else {
                     None
                 }
             })
+        })?
     }
 
-    pub fn get_user_by_first_name(&self, firstname: &str) -> Option<(&String, &UserEntity)> {
-        self.bot_db
-            .bot_memory
-            .user_map
-            .iter()
-            .find_map(|(index, user)| {
+    pub fn get_user_by_first_name(
+        &self,
+        chat_id: &str,
+        firstname: &str,
+    ) -> Option<(String, UserEntity)> {
+        self.read_chat(chat_id, |managed_chat| {
+            managed_chat.user_map.iter().find_map(|(index, user)| {
                 if user.firstname.to_lowercase() == firstname.to_lowercase() {
-                    Some((index, user))
+                    Some((index.clone(), user.clone()))
                 } else {
                     None
                 }
             })
+        })?
     }
 
-    pub fn register_administrator(&mut self, username: String) {
-        debug!("Registering administrator: {}", username);
-        self.bot_db.bot_memory.administrators.push(username);
+    /// Replaces the administrators of a chat at once, so admin checks never see a partial list.
+    pub fn set_administrators(&self, chat_id: &str, administrators: Vec<i64>) {
+        debug!(
+            "Registering administrators of chat {}: {:?}",
+            chat_id, administrators
+        );
+        self.write_chat(chat_id, |managed_chat| {
+            managed_chat.administrators = administrators;
+        });
     }
 
-    pub fn determine_user_role(&self, username: &str) -> &str {
-        if self
-            .bot_db
-            .bot_memory
-            .administrators
-            .contains(&username.to_string())
-        {
+    pub fn determine_user_role(&self, chat_id: &str, user_id: i64) -> &'static str {
+        if self.is_administrator(chat_id, user_id) {
             "Admin"
         } else {
             "Regular User"
         }
     }
 
-    pub fn is_administrator(&self, username: &str) -> bool {
+    pub fn is_administrator(&self, chat_id: &str, user_id: i64) -> bool {
+        self.read_chat(chat_id, |managed_chat| {
+            managed_chat.administrators.contains(&user_id)
+        })
+        .unwrap_or(false)
+    }
+
+    pub fn get_administrators(&self, chat_id: &str) -> Vec<i64> {
+        self.read_chat(chat_id, |managed_chat| managed_chat.administrators.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns the managed chats where the user is administrator, used for admin commands
+    /// which are sent outside of a managed chat.
+    pub fn get_administrated_chat_ids(&self, user_id: i64) -> Vec<String> {
         self.bot_db
+            .read()
+            .unwrap()
             .bot_memory
-            .administrators
-            .contains(&username.to_string())
+            .managed_chats
+            .iter()
+            .filter(|(_, managed_chat)| managed_chat.administrators.contains(&user_id))
+            .map(|(chat_id, _)| chat_id.clone())
+            .collect()
+    }
+
+    pub fn get_persona(&self, chat_id: &str) -> Option<PersonaEntity> {
+        self.read_chat(chat_id, |managed_chat| managed_chat.persona.clone())
     }
 
-    pub fn clear_administrators(&mut self) {
-        self.bot_db.bot_memory.administrators.clear();
+    pub fn set_persona(&self, chat_id: &str, persona: PersonaEntity) {
+        self.write_chat(chat_id, |managed_chat| {
+            managed_chat.persona = persona;
+        });
     }
 
-    pub fn set_managed_chat_id(&mut self, chat_id: Option<String>) {
-        self.bot_db.bot_memory.managed_chat_id = chat_id;
+    /// Copy of the bot memory restricted to the given managed chats.
+    pub fn export_managed_chats(&self, chat_ids: &[String]) -> BotMemoryEntity {
+        let mut bot_memory = self.bot_db.read().unwrap().bot_memory.clone();
+        bot_memory
+            .managed_chats
+            .retain(|chat_id, _| chat_ids.contains(chat_id));
+        bot_memory
     }
 
-    pub fn persist(&mut self) {
-        if let Err(e) = self.bot_db.save() {
+    /// Merges an imported chat state into a managed chat, known users are only replaced
+    /// by more recent activity. Returns the number of added or updated users. Imported
+    /// administrators are ignored, admins only come from Telegram via /init. Users filed under
+    /// another id than their own are skipped, as are sanctions of unknown kinds. Activity in the
+    /// future is clamped to now.
+    pub fn merge_managed_chat(&self, chat_id: &str, imported: ManagedChatEntity) -> usize {
+        let current_time: u64 = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.write_chat(chat_id, |managed_chat| {
+            let mut merged_users: usize = 0;
+            for (user_key, mut imported_user) in imported.user_map {
+                if user_key != imported_user.user_id.to_string() {
+                    debug!(
+                        "Skipping imported user {} filed under {}",
+                        imported_user.user_id, user_key
+                    );
+                    continue;
+                }
+                imported_user
+                    .sanctions
+                    .retain(|sanction| SANCTION_KINDS.contains(&sanction.kind.as_str()));
+                imported_user.last_activity_unix_time =
+                    imported_user.last_activity_unix_time.min(current_time);
+                let is_newer = managed_chat.user_map.get(&user_key).is_none_or(|user| {
+                    user.last_activity_unix_time < imported_user.last_activity_unix_time
+                });
+                if is_newer {
+                    managed_chat.user_map.insert(user_key, imported_user);
+                    merged_users += 1;
+                }
+            }
+            if managed_chat.persona == PersonaEntity::default() {
+                managed_chat.persona = imported.persona;
+            }
+            merged_users
+        })
+        .unwrap_or(0)
+    }
+
+    pub fn persist(&self) {
+        if let Err(e) = self.bot_db.read().unwrap().save() {
             debug!("Could not save cause: {}", e);
         }
     }
+
+    pub fn persist_user(&self, chat_id: &str, user_id: i64) {
+        if let Err(e) = self.bot_db.read().unwrap().save_user(chat_id, user_id) {
+            debug!("Could not save user {} cause: {}", user_id, e);
+        }
+    }
 }
@@ -1,10 +1,30 @@
+mod degraded_filter;
+mod localization;
 mod member;
+mod moderation_verdict;
 mod moderator_agent;
+mod persona;
 pub mod tools;
+pub use degraded_filter::DegradedModeFilter;
+pub use localization::configured_locale;
+pub use localization::Locale;
+pub use localization::Text;
 pub use member::UserManagement;
+pub use moderation_verdict::ModerationVerdict;
+pub use moderation_verdict::VerdictAction;
+pub use moderation_verdict::VerdictReplyExtractor;
+pub use moderation_verdict::VerdictSeverity;
+pub use moderator_agent::history_key;
+pub use moderator_agent::read_prompt_template;
+pub use moderator_agent::validate_prompt_template;
 pub use moderator_agent::Moderator;
+pub use moderator_agent::ReplyPart;
 pub use moderator_agent::MODERATOR_PROMPT_FILE;
-pub use moderator_agent::NO_ACTION;
+pub use persona::describe_persona;
+pub use persona::persona_prompt_section;
+pub use persona::set_persona_field;
+pub use persona::validate_persona;
+pub use persona::PERSONA_FIELDS;
 
 use serde::{Deserialize, Serialize};
 
@@ -19,11 +39,3 @@ pub struct UserMessage {
     pub message_id: i64,
     pub date_unix_time: String,
 }
-
-#[derive(Clone, Serialize, Deserialize)]
-pub struct ModeratorMessage {
-    pub user_id: String,
-    pub chat_id: String,
-    pub moderator: String,
-    pub message: String,
-}
@@ -0,0 +1,202 @@
+use anyhow::anyhow;
+use schemars::{generate::SchemaSettings, JsonSchema, Schema};
+use serde::{Deserialize, Serialize};
+
+// Warnings and escalations below this confidence are only answered with the reply text
+const MIN_SANCTION_CONFIDENCE: f32 = 0.7;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum VerdictAction {
+    None,
+    Reply,
+    Warn,
+    Escalate,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum VerdictSeverity {
+    None,
+    Low,
+    Medium,
+    High,
+}
+
+/// Decision of the moderator on an incoming message, the model answers with this JSON document.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ModerationVerdict {
+    #[schemars(
+        description = "none: stay silent, reply: answer the message, warn: warn the author for a rule violation, escalate: notify the admins"
+    )]
+    pub action: VerdictAction,
+
+    #[schemars(description = "Number of the violated group rule, null if no rule is violated.")]
+    pub violated_rule: Option<u32>,
+
+    #[schemars(description = "Severity of the rule violation.")]
+    pub severity: VerdictSeverity,
+
+    #[schemars(description = "Confidence in this verdict between 0.0 and 1.0.")]
+    pub confidence: f32,
+
+    #[schemars(description = "Message sent to the chat, empty for the action none.")]
+    pub reply: String,
+
+    /// Thinking of the model before it answered, not part of the answer.
+    #[serde(skip)]
+    pub reasoning: Option<String>,
+}
+
+impl ModerationVerdict {
+    /// JSON schema the model output is constrained to. Subschemas are inlined, since not every
+    /// backend resolves references in structured output schemas.
+    pub fn schema() -> Schema {
+        SchemaSettings::draft07()
+            .with(|settings| settings.inline_subschemas = true)
+            .into_generator()
+            .into_root_schema_for::<ModerationVerdict>()
+    }
+
+    /// Parses and validates the answer of the model. Some models wrap JSON answers in a
+    /// markdown code block, which is tolerated.
+    pub fn parse(content: &str) -> Result<Self, anyhow::Error> {
+        let json: &str = content
+            .trim()
+            .trim_start_matches("```json")
+            .trim_start_matches("```")
+            .trim_end_matches("```")
+            .trim();
+        let verdict: ModerationVerdict = serde_json::from_str(json)?;
+        verdict.validate()?;
+        Ok(verdict)
+    }
+
+    fn validate(&self) -> Result<(), anyhow::Error> {
+        if !(0.0..=1.0).contains(&self.confidence) {
+            return Err(anyhow!(
+                "confidence {} is not between 0.0 and 1.0",
+                self.confidence
+            ));
+        }
+        let needs_reply: bool = matches!(self.action, VerdictAction::Reply | VerdictAction::Warn);
+        if needs_reply && self.reply.trim().is_empty() {
+            return Err(anyhow!("action {:?} requires a reply", self.action));
+        }
+        if self.action == VerdictAction::Warn && self.violated_rule.is_none() {
+            return Err(anyhow!("action warn requires the violated rule"));
+        }
+        Ok(())
+    }
+
+    /// Action to carry out. Warnings and escalations the model isn't confident about are
+    /// downgraded to a plain reply.
+    pub fn effective_action(&self) -> VerdictAction {
+        match self.action {
+            VerdictAction::Warn | VerdictAction::Escalate
+                if self.confidence < MIN_SANCTION_CONFIDENCE =>
+            {
+                VerdictAction::Reply
+            }
+            action => action,
+        }
+    }
+}
+
+/// Picks the reply text out of a streamed verdict, so it can be shown while the model is still
+/// generating the JSON document.
+#[derive(Default)]
+pub struct VerdictReplyExtractor {
+    text: String,
+    forwarded_length: usize,
+}
+
+impl VerdictReplyExtractor {
+    /// Consumes the next part of the verdict and returns the reply text it adds.
+    pub fn push(&mut self, part: &str) -> String {
+        self.text.push_str(part);
+        let Some(reply) = partial_reply(&self.text) else {
+            return String::new();
+        };
+        if reply.len() <= self.forwarded_length {
+            return String::new();
+        }
+        let added_text: String = reply[self.forwarded_length..].to_string();
+        self.forwarded_length = reply.len();
+        added_text
+    }
+}
+
+/// Decodes the reply string of an incomplete verdict as far as it has been received.
+fn partial_reply(text: &str) -> Option<String> {
+    // The action may be "reply" as well, the key is the one followed by a colon
+    let value: &str = text
+        .match_indices("\"reply\"")
+        .find_map(|(index, key)| text[index + key.len()..].trim_start().strip_prefix(':'))?;
+    let raw: &str = value.trim_start().strip_prefix('"')?;
+
+    // Cut before the closing quote or before an escape sequence which is not complete yet
+    let bytes: &[u8] = raw.as_bytes();
+    let mut end: usize = 0;
+    while end < bytes.len() && bytes[end] != b'"' {
+        if bytes[end] == b'\\' {
+            let escape_length: usize = if bytes.get(end + 1) == Some(&b'u') {
+                6
+            } else {
+                2
+            };
+            if end + escape_length > bytes.len() {
+                break;
+            }
+            end += escape_length;
+        } else {
+            end += 1;
+        }
+    }
+    // Fails for the first half of a surrogate pair, which is decoded with the next part
+    serde_json::from_str(&format!("\"{}\"", &raw[..end])).ok()
+}
+
+#[cfg(test)]
+mod moderation_verdict_test {
+    use crate::application::moderation_verdict::{
+        ModerationVerdict, VerdictAction, VerdictReplyExtractor,
+    };
+
+    #[test]
+    fn should_parse_and_validate_verdicts() {
+        let verdict = ModerationVerdict::parse(
+            "```json\n{\"action\":\"warn\",\"violated_rule\":4,\"severity\":\"medium\",\"confidence\":0.9,\"reply\":\"Bitte keine Beleidigungen!\"}\n```",
+        )
+        .unwrap();
+        assert_eq!(verdict.effective_action(), VerdictAction::Warn);
+
+        let unsure_verdict = ModerationVerdict::parse(
+            r#"{"action":"escalate","violated_rule":null,"severity":"high","confidence":0.4,"reply":""}"#,
+        )
+        .unwrap();
+        assert_eq!(unsure_verdict.effective_action(), VerdictAction::Reply);
+
+        assert!(ModerationVerdict::parse(
+            r#"{"action":"warn","violated_rule":null,"severity":"low","confidence":0.9,"reply":"Stop"}"#
+        )
+        .is_err());
+        assert!(ModerationVerdict::parse("NO_ACTION").is_err());
+    }
+
+    #[test]
+    fn should_extract_reply_from_streamed_verdict() {
+        let mut extractor = VerdictReplyExtractor::default();
+        let parts = [
+            "{\"action\":\"reply\",\"re",
+            "ply\": \"Hallo \\",
+            "\"Sabine\\",
+            "\" \\u00e4",
+            "\", \"confidence\": 1.0}",
+        ];
+
+        let reply: String = parts.iter().map(|part| extractor.push(part)).collect();
+
+        assert_eq!(reply, "Hallo \"Sabine\" ä");
+    }
+}
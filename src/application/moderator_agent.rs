@@ -1,73 +1,440 @@
-use log::debug;
-use ollama_rs::{
-    generation::{
-        chat::{request::ChatMessageRequest, ChatMessage},
-        parameters::FormatType,
-        tools::{ToolFunctionInfo, ToolInfo, ToolType},
-    },
-    Ollama,
+use futures::StreamExt;
+use log::{debug, warn};
+use ollama_rs::generation::{
+    chat::{ChatMessage, MessageRole},
+    tools::{ToolFunctionInfo, ToolInfo, ToolType},
 };
 use schemars::Schema;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
 use std::env;
-use std::{collections::VecDeque, vec};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio::sync::mpsc::UnboundedSender;
 
-use crate::application::tools::execute_tool;
+use crate::adapter::{
+    configured_context_window, estimate_tokens, open_configured_llm_provider, AuditEntryEntity,
+    AuditRepository, ChatOptions, HistoryEntryEntity, HistoryRepository, LlmOperation, LlmProvider,
+    MemoryEntryEntity, PersonaEntity, SemanticMemoryRepository, ThinkSettings,
+    HISTORY_CONTEXT_WINDOW_DIVISOR,
+};
+use crate::application::tools::{execute_tool, is_moderation_tool, ToolOutcome};
+use crate::application::{
+    configured_locale, persona_prompt_section, Locale, ModerationVerdict, UserManagement,
+    VerdictReplyExtractor,
+};
 
-const MAX_HISTORY_BUFFER_SIZE: usize = 60;
+const MIN_HISTORY_TOKEN_BUDGET: usize = 512;
+const MEMORY_SEARCH_LIMIT: usize = 5;
+const MIN_REMEMBERED_MESSAGE_LENGTH: usize = 20;
+const MEMORY_PROMPT: &str =
+    "Relevant notes from earlier conversations of this chat, use them only if they help:";
+const SUMMARY_PREFIX: &str = "Summary of the earlier conversation:\n";
+const COMPACTION_PROMPT: &str = "You maintain the running summary of a group chat. Merge the previous summary, if given, and the following messages into a new concise summary. Keep decisions, agreements, warnings, sanctions and open questions together with the names of the people involved. Reply with the summary only.";
+const MAX_RECORDED_TOOL_RESULT_LENGTH: usize = 1000;
+const MAX_TOOL_ROUNDS_ENV: &str = "LLM_MAX_TOOL_ROUNDS";
+const DEFAULT_MAX_TOOL_ROUNDS: usize = 5;
+const TOOL_ROUND_LIMIT_MESSAGE: &str =
+    "You have used too many tools. Answer now with the information you already have.";
+const MALFORMED_VERDICT_MESSAGE: &str =
+    "Your answer is not a valid verdict. Answer again with a JSON document following the verdict schema only. Error:";
 pub const NO_ACTION: &str = "NO_ACTION";
 pub const MODERATOR_PROMPT_FILE: &str = "./prompts/moderator_role_definition.md";
+const REQUIRED_PROMPT_PLACEHOLDERS: [&str; 3] = ["{name}", "{username}", NO_ACTION];
+
+/// Part of an answer streamed to the chat while the LLM is generating it.
+#[derive(Debug, PartialEq)]
+pub enum ReplyPart {
+    Text(String),
+    /// The text streamed so far belongs to an attempt which has been superseded, e.g. by a tool
+    /// round or by a retry, and is replaced by the text which follows.
+    Discard,
+}
+
+fn discard_streamed_reply(reply_sender: Option<&UnboundedSender<ReplyPart>>) {
+    if let Some(reply_sender) = reply_sender {
+        let _ = reply_sender.send(ReplyPart::Discard);
+    }
+}
+
+/// Key of the history of a chat or, in forum groups, of a single topic.
+pub fn history_key(chat_id: &str, message_thread_id: Option<i64>) -> String {
+    match message_thread_id {
+        Some(message_thread_id) => format!("{}:{}", chat_id, message_thread_id),
+        None => chat_id.to_string(),
+    }
+}
+
+fn chat_id_of_history_key(key: &str) -> &str {
+    key.split(':').next().unwrap_or(key)
+}
+
+/// Token budget of the conversation history, what's left of its share of the context window
+/// after the system prompt. Fallback models with a smaller context window get the history
+/// trimmed to theirs, see `fit_context_window`.
+fn history_token_budget(initial_prompt_messages: &[ChatMessage], context_window: usize) -> usize {
+    let prompt_tokens: usize = initial_prompt_messages.iter().map(estimate_tokens).sum();
+    (context_window / HISTORY_CONTEXT_WINDOW_DIVISOR)
+        .saturating_sub(prompt_tokens)
+        .max(MIN_HISTORY_TOKEN_BUDGET)
+}
 
-#[derive(Clone, Default)]
+#[derive(Clone)]
 pub struct HistoryBuffer {
-    history_queue: VecDeque<ChatMessage>,
+    history_queue: VecDeque<HistoryEntryEntity>,
     initial_prompt_messages: Vec<ChatMessage>,
+    token_budget: usize,
 }
 
 impl HistoryBuffer {
-    pub fn new(messages: Vec<ChatMessage>) -> Self {
+    pub fn new(messages: Vec<ChatMessage>, token_budget: usize) -> Self {
         Self {
             history_queue: VecDeque::new(),
             initial_prompt_messages: messages,
+            token_budget,
         }
     }
 
-    pub fn set_message_adjust_buffer(&mut self, messages: Vec<ChatMessage>) {
-        self.history_queue = VecDeque::from(messages);
+    pub fn restore(&mut self, entries: Vec<HistoryEntryEntity>) {
+        self.history_queue = VecDeque::from(entries);
+        self.drop_orphaned_tool_results();
+    }
+
+    /// Creation time of the oldest message, `u64::MAX` if the history is empty.
+    pub fn oldest_unix_time(&self) -> u64 {
+        self.history_queue
+            .get(self.first_message_index())
+            .map(|entry| entry.created_unix_time)
+            .unwrap_or(u64::MAX)
+    }
 
-        let mut initial_promt_counter = self.initial_prompt_messages.len();
-        while initial_promt_counter > 0 {
-            self.history_queue.pop_front();
-            initial_promt_counter -= 1;
+    /// Index of the oldest message, behind the running summary.
+    fn first_message_index(&self) -> usize {
+        match self.history_queue.front() {
+            Some(entry) if entry.summary => 1,
+            _ => 0,
         }
+    }
 
-        if self.history_queue.len() > MAX_HISTORY_BUFFER_SIZE {
-            self.history_queue.pop_front();
+    /// Removes the tool results at the beginning of the history whose tool call is gone,
+    /// models reject tool results without a preceding call.
+    fn drop_orphaned_tool_results(&mut self) -> Vec<HistoryEntryEntity> {
+        let index = self.first_message_index();
+        let mut dropped = Vec::new();
+        while self
+            .history_queue
+            .get(index)
+            .is_some_and(|entry| entry.message.role == MessageRole::Tool)
+        {
+            dropped.extend(self.history_queue.remove(index));
         }
+        dropped
+    }
+
+    fn estimated_tokens(&self) -> usize {
+        self.history_queue
+            .iter()
+            .map(|entry| estimate_tokens(&entry.message))
+            .sum()
+    }
+
+    pub fn exceeds_token_budget(&self) -> bool {
+        self.estimated_tokens() > self.token_budget
+    }
+
+    /// Removes the oldest messages until the history fits into half of its token budget.
+    /// Returns the running summary and the removed messages, to be compacted into a new summary.
+    pub fn take_compactable(&mut self) -> (Option<String>, Vec<HistoryEntryEntity>) {
+        let summary: Option<String> = match self.history_queue.front() {
+            Some(entry) if entry.summary => self.history_queue.pop_front().map(|entry| {
+                entry
+                    .message
+                    .content
+                    .trim_start_matches(SUMMARY_PREFIX)
+                    .to_string()
+            }),
+            _ => None,
+        };
+        let mut removed = Vec::new();
+        while self.estimated_tokens() > self.token_budget / 2 {
+            match self.history_queue.pop_front() {
+                Some(entry) => removed.push(entry),
+                None => break,
+            }
+        }
+        removed.extend(self.drop_orphaned_tool_results());
+        (summary, removed)
+    }
+
+    /// Puts messages taken by `take_compactable` back in front of the history.
+    pub fn put_back(&mut self, entries: Vec<HistoryEntryEntity>) {
+        for entry in entries.into_iter().rev() {
+            self.history_queue.push_front(entry);
+        }
+    }
+
+    /// Puts the running summary in front of the remaining messages.
+    pub fn set_summary(&mut self, summary: &str) {
+        let now: u64 = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.history_queue
+            .push_front(HistoryEntryEntity::new_summary(
+                now,
+                ChatMessage::system(format!("{}{}", SUMMARY_PREFIX, summary.trim())),
+            ));
+    }
+
+    /// Takes over the messages appended to the result of `get_history`.
+    pub fn set_message_adjust_buffer(&mut self, messages: Vec<ChatMessage>) {
+        let now: u64 = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let known_messages = self.initial_prompt_messages.len() + self.history_queue.len();
+        messages
+            .into_iter()
+            .skip(known_messages)
+            .map(condense_tool_result)
+            .for_each(|message| {
+                self.history_queue
+                    .push_back(HistoryEntryEntity::new(now, message))
+            });
+    }
+
+    /// Swaps the system prompt, the recorded conversation is kept.
+    pub fn replace_initial_prompt_messages(
+        &mut self,
+        messages: Vec<ChatMessage>,
+        token_budget: usize,
+    ) {
+        self.initial_prompt_messages = messages;
+        self.token_budget = token_budget;
+    }
+
+    pub fn get_entries(&self) -> impl Iterator<Item = &HistoryEntryEntity> {
+        self.history_queue.iter()
     }
 
     pub fn get_history(&self) -> Vec<ChatMessage> {
         [
             self.initial_prompt_messages.clone(),
-            Vec::from(self.history_queue.clone()),
+            self.get_chat_history_only(),
         ]
         .concat()
     }
 
-    pub fn get_initial_prompt_messages(&self) -> Vec<ChatMessage> {
-        self.initial_prompt_messages.clone()
+    pub fn get_chat_history_only(&self) -> Vec<ChatMessage> {
+        self.history_queue
+            .iter()
+            .map(|entry| entry.message.clone())
+            .collect()
     }
+}
 
-    pub fn get_chat_history_only(&self) -> Vec<ChatMessage> {
-        self.history_queue.clone().into()
+/// Field of the JSON encoded `UserMessage` passed into `chat_forum`.
+fn user_message_field(input_json: &str, field: &str) -> Option<String> {
+    serde_json::from_str::<Value>(input_json)
+        .ok()
+        .and_then(|message| message.get(field)?.as_str().map(String::from))
+}
+
+fn format_memories(memories: &[MemoryEntryEntity]) -> String {
+    let now: u64 = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let mut prompt = String::from(MEMORY_PROMPT);
+    for memory in memories {
+        let age_days: u64 = now.saturating_sub(memory.created_unix_time) / (24 * 60 * 60);
+        let source: String = match memory.kind.as_str() {
+            "fact" => format!("fact noted by {}", memory.author),
+            _ => format!("{} wrote", memory.author),
+        };
+        prompt.push_str(&format!(
+            "\n- {} days ago, {}: {}",
+            age_days, source, memory.text
+        ));
+    }
+    prompt
+}
+
+/// Tool results like web pages can be huge, the history keeps only their beginning.
+fn condense_tool_result(mut message: ChatMessage) -> ChatMessage {
+    if message.role == MessageRole::Tool
+        && message.content.chars().count() > MAX_RECORDED_TOOL_RESULT_LENGTH
+    {
+        message.content = message
+            .content
+            .chars()
+            .take(MAX_RECORDED_TOOL_RESULT_LENGTH)
+            .collect();
+        message.content.push_str(" …");
     }
+    message
 }
 
-#[derive(Clone, Default)]
+/// System prompts and the conversation histories built from them. They are locked together,
+/// so a history is never created or written back with a prompt which has been replaced.
+#[derive(Default)]
+struct ConversationState {
+    name: String,
+    bot_username: String,
+    prompt_template: String,
+    chat_personas: HashMap<String, PersonaEntity>,
+    initial_prompt_messages: Vec<ChatMessage>,
+    history_buffers: HashMap<String, HistoryBuffer>,
+    /// Context window of the primary model the histories are sized for.
+    context_window: usize,
+}
+
+impl ConversationState {
+    fn new(
+        name: &str,
+        bot_username: &str,
+        moderator_prompt_template: &str,
+        context_window: usize,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            bot_username: bot_username.to_string(),
+            prompt_template: moderator_prompt_template.to_string(),
+            chat_personas: HashMap::new(),
+            initial_prompt_messages: vec![ChatMessage::system(assemble_moderator_prompt_template(
+                name,
+                bot_username,
+                moderator_prompt_template,
+            ))],
+            history_buffers: HashMap::new(),
+            context_window,
+        }
+    }
+
+    /// System prompt of a chat, assembled from its persona if it has one.
+    fn chat_prompt_messages(&self, chat_id: &str) -> Vec<ChatMessage> {
+        let Some(persona) = self.chat_personas.get(chat_id) else {
+            return self.initial_prompt_messages.clone();
+        };
+        let prompt_template: String = format!(
+            "{}\n\n{}",
+            persona
+                .prompt_template
+                .as_deref()
+                .unwrap_or(&self.prompt_template)
+                .trim(),
+            persona_prompt_section(persona)
+        );
+        vec![ChatMessage::system(assemble_moderator_prompt_template(
+            persona.name.as_deref().unwrap_or(&self.name),
+            &self.bot_username,
+            &prompt_template,
+        ))]
+    }
+
+    fn replace_history_prompts(&mut self, is_affected: impl Fn(&str) -> bool) {
+        let affected_keys: Vec<String> = self
+            .history_buffers
+            .keys()
+            .filter(|key| is_affected(key))
+            .cloned()
+            .collect();
+        for key in affected_keys {
+            let messages = self.chat_prompt_messages(chat_id_of_history_key(&key));
+            let token_budget: usize = history_token_budget(&messages, self.context_window);
+            if let Some(history_buffer) = self.history_buffers.get_mut(&key) {
+                history_buffer.replace_initial_prompt_messages(messages, token_budget);
+            }
+        }
+    }
+
+    /// History of a key, restored from the last snapshot if it isn't loaded yet. Each managed
+    /// chat and each forum topic has its own history, see `history_key`.
+    fn history_buffer(
+        &mut self,
+        history_repository: &HistoryRepository,
+        key: &str,
+    ) -> &mut HistoryBuffer {
+        if !self.history_buffers.contains_key(key) {
+            let messages = self.chat_prompt_messages(chat_id_of_history_key(key));
+            let token_budget: usize = history_token_budget(&messages, self.context_window);
+            let mut history_buffer = HistoryBuffer::new(messages, token_budget);
+            history_buffer.restore(history_repository.load(key));
+            self.history_buffers.insert(key.to_string(), history_buffer);
+        }
+        self.history_buffers.get_mut(key).unwrap()
+    }
+}
+
+/// A sanction a moderation tool call of the LLM has imposed on a member.
+pub struct ImposedSanction {
+    pub user_id: i64,
+    pub action: AuditEntryEntity,
+}
+
+#[derive(Clone)]
 pub struct Moderator {
-    model_name: String,
-    ollama: Ollama,
-    history_buffer: HistoryBuffer,
+    llm: Arc<dyn LlmProvider>,
+    conversations: Arc<Mutex<ConversationState>>,
+    history_repository: HistoryRepository,
+    audit_repository: AuditRepository,
+    imposed_sanctions: Arc<Mutex<Vec<ImposedSanction>>>,
     tool_infos: Vec<ToolInfo>,
+    user_management: UserManagement,
+    max_tool_rounds: usize,
+    semantic_memory: Option<SemanticMemoryRepository>,
+    think_settings: ThinkSettings,
+}
+
+impl Default for Moderator {
+    fn default() -> Self {
+        let llm: Arc<dyn LlmProvider> = open_configured_llm_provider();
+        let context_window: usize = configured_context_window(llm.model_name());
+        Self {
+            llm,
+            conversations: Arc::new(Mutex::new(ConversationState {
+                context_window,
+                ..ConversationState::default()
+            })),
+            history_repository: HistoryRepository::default(),
+            audit_repository: AuditRepository::default(),
+            imposed_sanctions: Arc::new(Mutex::new(Vec::new())),
+            tool_infos: Vec::default(),
+            user_management: UserManagement::default(),
+            max_tool_rounds: configured_max_tool_rounds(),
+            semantic_memory: None,
+            think_settings: ThinkSettings::default(),
+        }
+    }
+}
+
+fn configured_max_tool_rounds() -> usize {
+    env::var(MAX_TOOL_ROUNDS_ENV)
+        .ok()
+        .and_then(|rounds| rounds.parse().ok())
+        .unwrap_or(DEFAULT_MAX_TOOL_ROUNDS)
+}
+
+/// Reads the moderator prompt template and checks it for the required placeholders.
+pub fn read_prompt_template(path: &str) -> std::result::Result<String, anyhow::Error> {
+    let template: String = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read the prompt template file {}: {}", path, e))?;
+    validate_prompt_template(&template)?;
+    Ok(template)
+}
+
+pub fn validate_prompt_template(template: &str) -> std::result::Result<(), anyhow::Error> {
+    let missing_placeholders: Vec<&str> = REQUIRED_PROMPT_PLACEHOLDERS
+        .into_iter()
+        .filter(|placeholder| !template.contains(placeholder))
+        .collect();
+    if !missing_placeholders.is_empty() {
+        return Err(anyhow::anyhow!(
+            "The prompt template lacks the placeholders {}",
+            missing_placeholders.join(", ")
+        ));
+    }
+    Ok(())
 }
 
 fn assemble_moderator_prompt_template(
@@ -100,8 +467,27 @@ fn assemble_moderator_prompt_template(
         )
         .as_str(),
     );
-    moderator_template
-        .push_str("You should always respond in plain text. Don't respond in JSON format.\n\n");
+    moderator_template.push_str("\n\n## Verdict\n\n");
+    moderator_template.push_str(
+        format!(
+            "**Answer every message with a verdict, a JSON document following this schema**: \n\n{}\n",
+            serde_json::to_string(&ModerationVerdict::schema()).unwrap_or_default()
+        )
+        .as_str(),
+    );
+    moderator_template.push_str(
+        format!(
+            r#"           - **none**: The message needs no answer, this is what {} means. Leave the reply empty.
+           - **reply**: Answer the message with the reply text.
+           - **warn**: The author violated a group rule. Set violated_rule to the number of the rule and warn the author in the reply text.
+           - **escalate**: The message needs the attention of the Admins, e.g. threats or repeated violations. The Admins are notified, the reply text is sent to the chat if given.
+           - **confidence**: How sure you are about the verdict, between 0.0 and 1.0.
+
+"#,
+            NO_ACTION
+        )
+        .as_str(),
+    );
     moderator_template.push_str(
         r#"## You have access to the following Tools if an Admin or User is advising you:
            1. **mute_member_in_chat** (Only Admin are allowed to advise): Use this tool to mute a User from the chat. Extract the chat id from the chat_id property field, extract the name of the user and mute time from the admin message.
@@ -116,36 +502,81 @@ fn assemble_moderator_prompt_template(
            CRITICAL OPERATION RULES:
              - Never execute any tool without the explicit request from a User or Admin (except message_reaction tool). If you are not sure, ask to confirm or clarify their intention first.
              - Never write tool calls as plain text or guess arguments without clear intent.
+             - You receive the result of every tool call and may call further tools before you answer, e.g. get_member_info before mute_member_in_chat. Answer once with all results combined.
         "#);
     moderator_template.trim().to_string()
 }
 
 impl Moderator {
     pub fn new(name: &str, bot_username: &str, moderator_prompt_template: &str) -> Self {
-        let ollama_client = Ollama::builder()
-            .host(env::var("OLLAMA_HOST_ADDR").unwrap_or(String::from("http://localhost")))
-            .port(
-                env::var("OLLAMA_PORT")
-                    .unwrap_or(String::from("11434"))
-                    .parse()
-                    .unwrap(),
-            )
-            .build();
-        let model_name = env::var("LLM_MODEL").unwrap_or(String::from("mistral-nemo:12b"));
-
-        let messages = vec![ChatMessage::system(assemble_moderator_prompt_template(
-            name,
-            bot_username,
-            moderator_prompt_template,
-        ))];
-        let history_buffer = HistoryBuffer::new(messages);
+        let history_repository = HistoryRepository::try_init();
+        let llm: Arc<dyn LlmProvider> = open_configured_llm_provider();
+        let context_window: usize = configured_context_window(llm.model_name());
 
         Self {
-            model_name,
-            ollama: ollama_client,
-            history_buffer,
+            llm,
+            conversations: Arc::new(Mutex::new(ConversationState::new(
+                name,
+                bot_username,
+                moderator_prompt_template,
+                context_window,
+            ))),
+            history_repository,
+            audit_repository: AuditRepository::try_init(),
+            imposed_sanctions: Arc::new(Mutex::new(Vec::new())),
             tool_infos: Vec::default(),
+            user_management: UserManagement::default(),
+            max_tool_rounds: configured_max_tool_rounds(),
+            semantic_memory: SemanticMemoryRepository::try_init(),
+            think_settings: ThinkSettings::from_env(),
+        }
+    }
+
+    /// Assembles the system prompt from a new template, read by `read_prompt_template`, and
+    /// swaps it in every history without clearing the conversations. Chats with their own
+    /// template keep it.
+    pub fn reload_prompt_template(&self, moderator_prompt_template: &str) {
+        let mut conversations = self.conversations.lock().unwrap();
+        conversations.prompt_template = moderator_prompt_template.to_string();
+        conversations.initial_prompt_messages =
+            vec![ChatMessage::system(assemble_moderator_prompt_template(
+                &conversations.name,
+                &conversations.bot_username,
+                moderator_prompt_template,
+            ))];
+        conversations.replace_history_prompts(|_| true);
+    }
+
+    /// Uses the persona for the system prompt of the chat, a default persona restores the
+    /// bot-wide prompt.
+    pub fn set_chat_persona(&self, chat_id: &str, persona: PersonaEntity) {
+        let mut conversations = self.conversations.lock().unwrap();
+        if persona == PersonaEntity::default() {
+            conversations.chat_personas.remove(chat_id);
+        } else {
+            conversations
+                .chat_personas
+                .insert(chat_id.to_string(), persona);
         }
+        conversations.replace_history_prompts(|key| chat_id_of_history_key(key) == chat_id);
+    }
+
+    /// `false` while every LLM backend is failing, messages are moderated by the deterministic
+    /// filters then.
+    pub fn is_llm_available(&self) -> bool {
+        self.llm.is_available()
+    }
+
+    /// Language of a chat, chosen by its persona.
+    pub fn chat_locale(&self, chat_id: &str) -> Locale {
+        self.conversations
+            .lock()
+            .unwrap()
+            .chat_personas
+            .get(chat_id)
+            .and_then(|persona| persona.language.as_deref())
+            .and_then(Locale::from_code)
+            .unwrap_or_else(configured_locale)
     }
 
     /**
@@ -179,92 +610,427 @@ impl Moderator {
         self.tool_infos.push(tool_info);
     }
 
-    pub async fn chat_forum(
-        &mut self,
+    /// Members the tools look up, shared with the bot controller.
+    pub fn set_user_management(&mut self, user_management: UserManagement) {
+        self.user_management = user_management;
+    }
+
+    /// Records moderation tool calls of the LLM in the audit log of the chat and keeps the
+    /// sanctions which took effect.
+    fn audit_tool_call(
+        &self,
+        chat_id: &str,
+        tool_name: &str,
+        arguments: &Value,
         input_json: &str,
-    ) -> std::result::Result<String, anyhow::Error> {
-        let user_message = ChatMessage::user(input_json.to_string());
-        let mut history = self.history_buffer.get_history();
-        let response = self
-            .ollama
-            .send_chat_messages_with_history(
-                &mut history,
-                ChatMessageRequest::new(self.model_name.to_owned(), vec![user_message])
-                    .think(true)
-                    .format(FormatType::Json)
-                    .tools(self.tool_infos.clone()),
+        result: &str,
+        sanctioned_user_id: Option<i64>,
+    ) {
+        if !is_moderation_tool(tool_name) {
+            return;
+        }
+        let target: String = match arguments.get("name").or(arguments.get("message_id")) {
+            Some(Value::String(target)) => target.clone(),
+            Some(target) => target.to_string(),
+            None => String::new(),
+        };
+        let trigger_message: Option<String> = user_message_field(input_json, "message");
+        let audit_entry = AuditEntryEntity::new(
+            chat_id,
+            "LLM",
+            &target,
+            tool_name,
+            arguments.clone(),
+            trigger_message,
+            result,
+        );
+        if let Some(user_id) = sanctioned_user_id {
+            self.imposed_sanctions
+                .lock()
+                .unwrap()
+                .push(ImposedSanction {
+                    user_id,
+                    action: audit_entry.clone(),
+                });
+        }
+        self.audit_repository.record(audit_entry);
+    }
+
+    /// Sanctions imposed since the last call, so they can be applied to the member profiles.
+    pub fn take_imposed_sanctions(&self) -> Vec<ImposedSanction> {
+        std::mem::take(&mut *self.imposed_sanctions.lock().unwrap())
+    }
+
+    /// Options of a request without tools, thinking depends on the operation and the model.
+    fn chat_options(&self, operation: LlmOperation) -> ChatOptions {
+        ChatOptions {
+            think: self.think_settings.thinks(operation, self.llm.model_name()),
+            ..ChatOptions::default()
+        }
+    }
+
+    /// Sends the conversation to the LLM. With a reply sender the answer is streamed and its
+    /// content is forwarded part by part while the model is still generating. Of verdicts only
+    /// the reply text is forwarded.
+    async fn send_chat(
+        &self,
+        history: Vec<ChatMessage>,
+        options: ChatOptions,
+        reply_sender: Option<&UnboundedSender<ReplyPart>>,
+    ) -> std::result::Result<ChatMessage, anyhow::Error> {
+        let Some(reply_sender) = reply_sender else {
+            return self.llm.chat(history, options).await;
+        };
+        let mut reply_extractor_opt: Option<VerdictReplyExtractor> = options
+            .json_schema
+            .as_ref()
+            .map(|_| VerdictReplyExtractor::default());
+        let mut parts = self.llm.chat_stream(history, options).await?;
+        let mut response = ChatMessage::assistant(String::new());
+        while let Some(part) = parts.next().await {
+            let part = part?;
+            let reply_part: String = match reply_extractor_opt.as_mut() {
+                Some(reply_extractor) => reply_extractor.push(&part.content),
+                None => part.content.clone(),
+            };
+            if !reply_part.is_empty() {
+                let _ = reply_sender.send(ReplyPart::Text(reply_part));
+            }
+            response.content.push_str(&part.content);
+            response.tool_calls.extend(part.tool_calls);
+            if let Some(thinking) = part.thinking {
+                response
+                    .thinking
+                    .get_or_insert_with(String::new)
+                    .push_str(&thinking);
+            }
+        }
+        Ok(response)
+    }
+
+    /// Embeds the incoming message and looks up related memories which are no longer part of
+    /// the history. Returns the embedding, so the message can be remembered afterwards.
+    async fn recall_memories(
+        &self,
+        chat_id: &str,
+        key: &str,
+        input_json: &str,
+    ) -> Option<(Vec<f32>, Vec<MemoryEntryEntity>)> {
+        let semantic_memory = self.semantic_memory.as_ref()?;
+        let message: String = user_message_field(input_json, "message")?;
+        let embedding: Vec<f32> = match semantic_memory.embed(&message).await {
+            Ok(embedding) => embedding,
+            Err(e) => {
+                warn!("Could not embed message cause: {}", e);
+                return None;
+            }
+        };
+        let before_unix_time: u64 = self
+            .conversations
+            .lock()
+            .unwrap()
+            .history_buffer(&self.history_repository, key)
+            .oldest_unix_time();
+        let memories =
+            semantic_memory.search(chat_id, &embedding, before_unix_time, MEMORY_SEARCH_LIMIT);
+        Some((embedding, memories))
+    }
+
+    /// Adds an admin-provided fact to the semantic memory of a chat.
+    pub async fn remember_fact(
+        &self,
+        chat_id: &str,
+        author: &str,
+        fact: &str,
+    ) -> std::result::Result<(), anyhow::Error> {
+        let Some(semantic_memory) = &self.semantic_memory else {
+            return Err(anyhow::anyhow!("semantic memory is disabled"));
+        };
+        let embedding: Vec<f32> = semantic_memory.embed(fact).await?;
+        semantic_memory.remember(MemoryEntryEntity::new(
+            chat_id, author, "fact", fact, embedding,
+        ));
+        Ok(())
+    }
+
+    /// Compacts the oldest messages of a history beyond its token budget into the running
+    /// summary. If the LLM fails, the history is left as it was and the request is trimmed to
+    /// the context window by the provider, see `fit_context_window`.
+    async fn compact_history(&self, key: &str) {
+        let (summary_opt, removed) = {
+            let mut conversations = self.conversations.lock().unwrap();
+            let Some(history_buffer) = conversations.history_buffers.get_mut(key) else {
+                return;
+            };
+            if !history_buffer.exceeds_token_budget() {
+                return;
+            }
+            history_buffer.take_compactable()
+        };
+        let transcript: String = removed
+            .iter()
+            .map(|entry| {
+                let message: &ChatMessage = &entry.message;
+                let tool_calls: String = message
+                    .tool_calls
+                    .iter()
+                    .map(|call| {
+                        format!(
+                            " [calls {} with {}]",
+                            call.function.name, call.function.arguments
+                        )
+                    })
+                    .collect();
+                format!("{:?}: {}{}", message.role, message.content, tool_calls)
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        let mut compaction_messages = vec![ChatMessage::system(COMPACTION_PROMPT.to_string())];
+        if let Some(summary) = &summary_opt {
+            compaction_messages.push(ChatMessage::user(format!("Previous summary:\n{}", summary)));
+        }
+        compaction_messages.push(ChatMessage::user(format!("Messages:\n{}", transcript)));
+
+        let summary_rs = self
+            .llm
+            .chat(
+                compaction_messages,
+                self.chat_options(LlmOperation::Compaction),
             )
+            .await;
+        let mut conversations = self.conversations.lock().unwrap();
+        let Some(history_buffer) = conversations.history_buffers.get_mut(key) else {
+            return;
+        };
+        match (summary_rs, summary_opt) {
+            (Ok(response), _) => {
+                debug!("Compacted {} messages of history {}", removed.len(), key);
+                history_buffer.set_summary(&response.content);
+            }
+            (Err(e), summary_opt) => {
+                warn!("Could not compact history {} cause: {}", key, e);
+                history_buffer.put_back(removed);
+                if let Some(summary) = summary_opt {
+                    history_buffer.set_summary(&summary);
+                }
+            }
+        }
+        self.history_repository
+            .save(key, history_buffer.get_entries());
+    }
+
+    /// Decides on an incoming message. The model may call tools before it answers with a
+    /// verdict, a malformed verdict is asked for once more. Only the reply of the final attempt
+    /// is streamed to `reply_sender`. The reasoning of all rounds is returned with the verdict
+    /// instead of being kept in the history. The history is only
+    /// locked to take it and to record the answer, the caller ensures that messages of the
+    /// same history are decided one after another.
+    pub async fn chat_forum(
+        &self,
+        chat_id: &str,
+        message_thread_id: Option<i64>,
+        input_json: &str,
+        reply_sender: Option<UnboundedSender<ReplyPart>>,
+    ) -> std::result::Result<ModerationVerdict, anyhow::Error> {
+        let key: String = history_key(chat_id, message_thread_id);
+        self.conversations
+            .lock()
+            .unwrap()
+            .history_buffer(&self.history_repository, &key);
+        self.compact_history(&key).await;
+        let recalled_opt = self.recall_memories(chat_id, &key, input_json).await;
+        let mut history = self
+            .conversations
+            .lock()
+            .unwrap()
+            .history_buffer(&self.history_repository, &key)
+            .get_history();
+        // Recalled memories are passed for this message only and are not recorded
+        let memory_message_index_opt: Option<usize> = match &recalled_opt {
+            Some((_, memories)) if !memories.is_empty() => {
+                history.push(ChatMessage::system(format_memories(memories)));
+                Some(history.len() - 1)
+            }
+            _ => None,
+        };
+        history.push(ChatMessage::user(input_json.to_string()));
+        let verdict_options = ChatOptions {
+            json_schema: Some(ModerationVerdict::schema()),
+            ..self.chat_options(LlmOperation::Moderation)
+        };
+        let tool_options = ChatOptions {
+            tools: self.tool_infos.clone(),
+            ..verdict_options.clone()
+        };
+        let mut reasoning: Vec<String> = Vec::new();
+        let mut response = self
+            .send_chat(history.clone(), tool_options.clone(), reply_sender.as_ref())
             .await?;
+        reasoning.extend(response.thinking.take());
+        history.push(response.clone());
         debug!("History: {:#?}", history);
-        if !response.message.tool_calls.is_empty() {
-            let mut final_response_str: String = String::new();
-            history.push(response.message.clone());
 
-            for call in &response.message.tool_calls {
+        // Tool results go back to the model with tools still enabled, so it can chain tools
+        // until it answers or the round limit is reached
+        let mut tool_rounds: usize = 0;
+        while !response.tool_calls.is_empty() {
+            discard_streamed_reply(reply_sender.as_ref());
+            if tool_rounds >= self.max_tool_rounds {
+                debug!(
+                    "Tool round limit of {} reached, asking for a final answer",
+                    self.max_tool_rounds
+                );
+                // The request to answer is only sent to the model, the final answer replaces
+                // the unanswered tool calls in the history
+                let mut final_history = history.clone();
+                final_history.push(ChatMessage::user(TOOL_ROUND_LIMIT_MESSAGE.to_string()));
+                response = self
+                    .send_chat(
+                        final_history,
+                        verdict_options.clone(),
+                        reply_sender.as_ref(),
+                    )
+                    .await?;
+                reasoning.extend(response.thinking.take());
+                history.pop();
+                history.push(response.clone());
+                break;
+            }
+            tool_rounds += 1;
+
+            for call in &response.tool_calls {
                 let args = &call.function.arguments;
                 let name: String = call.function.name.clone();
-                let tool_response_rs = execute_tool(name.as_str(), args.clone()).await;
-                if let Ok(tool_rs) = tool_response_rs {
-                    history.push(ChatMessage::tool(tool_rs));
-                    let final_response = self
-                        .ollama
-                        .send_chat_messages(
-                            ChatMessageRequest::new(self.model_name.to_owned(), history.clone())
-                                .think(false),
-                        )
-                        .await?;
-                    final_response_str
-                        .push_str(format!("{}\n", &final_response.message.content).as_str());
-                } else {
-                    debug!(
-                        "Failed to execute tool cause: {}",
-                        tool_response_rs.err().unwrap()
-                    )
-                }
+                let tool_response_rs =
+                    execute_tool(name.as_str(), args.clone(), &self.user_management).await;
+                let tool_outcome: ToolOutcome = match tool_response_rs {
+                    Ok(tool_outcome) => tool_outcome,
+                    Err(e) => {
+                        debug!("Failed to execute tool cause: {}", e);
+                        format!("error: {}", e).into()
+                    }
+                };
+                self.audit_tool_call(
+                    chat_id,
+                    &name,
+                    args,
+                    input_json,
+                    &tool_outcome.result,
+                    tool_outcome.sanctioned_user_id,
+                );
+                history.push(ChatMessage::tool(tool_outcome.result));
+            }
+            response = self
+                .send_chat(history.clone(), tool_options.clone(), reply_sender.as_ref())
+                .await?;
+            reasoning.extend(response.thinking.take());
+            history.push(response.clone());
+        }
+        if tool_rounds > 0 {
+            debug!(
+                "Response after {} tool rounds - History: {:#?}",
+                tool_rounds, history
+            );
+        }
+        let mut verdict: ModerationVerdict = match ModerationVerdict::parse(&response.content) {
+            Ok(verdict) => verdict,
+            Err(e) => {
+                warn!("Malformed verdict {:?} cause: {}", response.content, e);
+                // The correction is only sent to the model, the retried verdict replaces the
+                // malformed one in the history
+                let mut retry_history = history.clone();
+                retry_history.push(ChatMessage::user(format!(
+                    "{} {}",
+                    MALFORMED_VERDICT_MESSAGE, e
+                )));
+                discard_streamed_reply(reply_sender.as_ref());
+                let mut retry_response = self
+                    .send_chat(retry_history, verdict_options, reply_sender.as_ref())
+                    .await?;
+                reasoning.extend(retry_response.thinking.take());
+                let verdict = ModerationVerdict::parse(&retry_response.content)?;
+                history.pop();
+                history.push(retry_response);
+                verdict
+            }
+        };
+        verdict.reasoning = Some(reasoning.join("\n\n")).filter(|reasoning| !reasoning.is_empty());
+        if let Some(memory_message_index) = memory_message_index_opt {
+            history.remove(memory_message_index);
+        }
+        if let (Some(semantic_memory), Some((embedding, _))) = (&self.semantic_memory, recalled_opt)
+        {
+            let message: String = user_message_field(input_json, "message").unwrap_or_default();
+            if message.chars().count() >= MIN_REMEMBERED_MESSAGE_LENGTH {
+                let author: String = user_message_field(input_json, "user").unwrap_or_default();
+                semantic_memory.remember(MemoryEntryEntity::new(
+                    chat_id, &author, "message", &message, embedding,
+                ));
             }
-            debug!("Response from tool - History: {:#?}", history);
-            return Ok(final_response_str);
         }
-        self.history_buffer.set_message_adjust_buffer(history);
-        Ok(response.message.content)
+        let mut conversations = self.conversations.lock().unwrap();
+        let history_buffer = conversations.history_buffer(&self.history_repository, &key);
+        history_buffer.set_message_adjust_buffer(history);
+        self.history_repository
+            .save(&key, history_buffer.get_entries());
+        Ok(verdict)
     }
 
-    pub async fn summarize_chat(&self, topic: &str) -> std::result::Result<String, anyhow::Error> {
+    pub async fn summarize_chat(
+        &self,
+        chat_id: &str,
+        message_thread_id: Option<i64>,
+        reply_sender: Option<UnboundedSender<ReplyPart>>,
+    ) -> std::result::Result<String, anyhow::Error> {
         let user_message = ChatMessage::user(format!(
-            "Only summarize the conversations from the channel: {}. Don't mention the channel name in the summary.",
-            topic
+            "Summarize the conversation above in {}. Don't mention the channel name in the summary.",
+            self.chat_locale(chat_id).language_name()
         ));
-        let mut history = self.history_buffer.get_chat_history_only();
+        let key: String = history_key(chat_id, message_thread_id);
+        let mut history: Vec<ChatMessage> = self
+            .conversations
+            .lock()
+            .unwrap()
+            .history_buffer(&self.history_repository, &key)
+            .get_chat_history_only();
         history.push(user_message);
         debug!("History: {:#?}", history);
 
         let response = self
-            .ollama
-            .send_chat_messages(
-                ChatMessageRequest::new(self.model_name.to_owned(), history).think(false),
+            .send_chat(
+                history,
+                self.chat_options(LlmOperation::Summary),
+                reply_sender.as_ref(),
             )
             .await?;
-        Ok(response.message.content)
+        Ok(response.content)
     }
 
-    pub async fn introduce_moderator(&self) -> std::result::Result<String, anyhow::Error> {
-        let mut history = self.history_buffer.get_initial_prompt_messages();
-        history.push(ChatMessage::user(
-            "Introduce yourself and tell the members what are the rules in this group in german"
-                .to_string(),
-        ));
+    pub async fn introduce_moderator(
+        &self,
+        chat_id: &str,
+        reply_sender: Option<UnboundedSender<ReplyPart>>,
+    ) -> std::result::Result<String, anyhow::Error> {
+        let mut history = self
+            .conversations
+            .lock()
+            .unwrap()
+            .chat_prompt_messages(chat_id);
+        history.push(ChatMessage::user(format!(
+            "Introduce yourself and tell the members what are the rules in this group in {}",
+            self.chat_locale(chat_id).language_name()
+        )));
 
         debug!("History: {:#?}", history);
 
         let response = self
-            .ollama
-            .send_chat_messages(
-                ChatMessageRequest::new(self.model_name.to_owned(), history)
-                    .think(false)
-                    .format(FormatType::Json),
+            .send_chat(
+                history,
+                self.chat_options(LlmOperation::Introduction),
+                reply_sender.as_ref(),
             )
             .await?;
-        Ok(response.message.content)
+        Ok(response.content)
     }
 }
 
@@ -276,7 +1042,7 @@ mod moderator_test {
     use mobot::init_logger;
 
     use crate::application::moderator_agent::{Moderator, MODERATOR_PROMPT_FILE};
-    use crate::application::UserMessage;
+    use crate::application::{UserMessage, VerdictAction};
 
     use super::*;
 
@@ -290,55 +1056,174 @@ mod moderator_test {
         }
     }
 
+    #[test]
+    fn should_record_tool_turns_in_history() {
+        let mut history_buffer =
+            HistoryBuffer::new(vec![ChatMessage::system("You are Kate".to_string())], 4096);
+        let mut tool_call_message = ChatMessage::assistant(String::new());
+        tool_call_message
+            .tool_calls
+            .push(ollama_rs::generation::tools::ToolCall {
+                function: ollama_rs::generation::tools::ToolCallFunction {
+                    name: "web_search".to_string(),
+                    arguments: serde_json::json!({ "query": "rust" }),
+                },
+            });
+        let mut history = history_buffer.get_history();
+        history.push(ChatMessage::user("Kate, what is rust?".to_string()));
+        history.push(tool_call_message);
+        history.push(ChatMessage::tool("x".repeat(5000)));
+        history.push(ChatMessage::assistant("Rust is a language".to_string()));
+
+        history_buffer.set_message_adjust_buffer(history);
+
+        let recorded = history_buffer.get_chat_history_only();
+        assert_eq!(recorded.len(), 4);
+        assert_eq!(recorded[1].tool_calls.len(), 1);
+        assert!(recorded[2].content.chars().count() < 5000);
+        assert_eq!(recorded[3].content, "Rust is a language");
+    }
+
+    #[test]
+    fn should_swap_system_prompt_and_keep_history() {
+        assert!(validate_prompt_template("You are {name}, answer NO_ACTION if in doubt").is_err());
+        assert!(validate_prompt_template("You are {name} ({username}), or NO_ACTION").is_ok());
+        let mut history_buffer =
+            HistoryBuffer::new(vec![ChatMessage::system("You are Kate".to_string())], 4096);
+        let mut history = history_buffer.get_history();
+        history.push(ChatMessage::user("Hallo Kate".to_string()));
+        history_buffer.set_message_adjust_buffer(history);
+
+        history_buffer.replace_initial_prompt_messages(
+            vec![ChatMessage::system("You are Lisa".to_string())],
+            2048,
+        );
+
+        let history = history_buffer.get_history();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].content, "You are Lisa");
+        assert_eq!(history[1].content, "Hallo Kate");
+    }
+
+    #[test]
+    fn should_build_history_of_clone_with_current_persona() {
+        let moderator = Moderator::new("Kate", "Kate", "You are {name} ({username}), or NO_ACTION");
+        let clone = moderator.clone();
+
+        moderator.set_chat_persona(
+            "-100",
+            PersonaEntity {
+                name: Some("Lisa".to_string()),
+                ..PersonaEntity::default()
+            },
+        );
+
+        let history = clone
+            .conversations
+            .lock()
+            .unwrap()
+            .history_buffer(&clone.history_repository, "-100")
+            .get_history();
+        assert!(history[0].content.contains("You are Lisa"));
+    }
+
+    #[test]
+    fn should_compact_oldest_messages_into_summary() {
+        let mut history_buffer = HistoryBuffer::new(Vec::new(), 100);
+        history_buffer.set_summary("Sabine organizes the meetup");
+        let mut history = history_buffer.get_history();
+        for i in 0..10 {
+            history.push(ChatMessage::user(format!("{} {}", i, "x".repeat(80))));
+        }
+        history_buffer.set_message_adjust_buffer(history);
+        assert!(history_buffer.exceeds_token_budget());
+
+        let (summary, removed) = history_buffer.take_compactable();
+        history_buffer.set_summary("Sabine organizes the meetup, the others join");
+
+        assert_eq!(summary.as_deref(), Some("Sabine organizes the meetup"));
+        assert!(removed[0].message.content.starts_with("0 "));
+        let remaining = history_buffer.get_chat_history_only();
+        assert!(remaining[0].content.ends_with("the others join"));
+        assert!(remaining.last().unwrap().content.starts_with("9 "));
+        assert!(!history_buffer.exceeds_token_budget());
+    }
+
+    #[test]
+    fn should_keep_messages_when_compaction_fails() {
+        let mut history_buffer = HistoryBuffer::new(Vec::new(), 100);
+        let mut history = history_buffer.get_history();
+        for i in 0..10 {
+            history.push(ChatMessage::user(format!("{} {}", i, "x".repeat(80))));
+        }
+        history_buffer.set_message_adjust_buffer(history);
+        let contents = |history_buffer: &HistoryBuffer| -> Vec<String> {
+            history_buffer
+                .get_chat_history_only()
+                .into_iter()
+                .map(|message| message.content)
+                .collect()
+        };
+        let before = contents(&history_buffer);
+
+        let (_, removed) = history_buffer.take_compactable();
+        assert!(!removed.is_empty());
+        history_buffer.put_back(removed);
+
+        assert_eq!(contents(&history_buffer), before);
+    }
+
     #[tokio::test]
     async fn should_test_moderator_successfully() {
-        let mut moderator =
+        let moderator =
             Moderator::new("Kate", "Kate", &read_prompt_template(MODERATOR_PROMPT_FILE));
         init_logger();
         let rs1 = moderator
-            .chat_forum(r#"{ "channel": "Play & Fun", "user_role": "Regular User", "user_id:" "1", "chat_id": "56789",  "user": "Sabine", "message": "Hallo Leute, gehts euch gut?" }"#)
+            .chat_forum("56789", None, r#"{ "channel": "Play & Fun", "user_role": "Regular User", "user_id:" "1", "chat_id": "56789",  "user": "Sabine", "message": "Hallo Leute, gehts euch gut?" }"#, None)
             .await;
         let rs2 = moderator
             .chat_forum(
-                r#"{ "channel": "Play & Fun", "user_role": "Regular User", "user_id:" "2", "chat_id": "56789", "user": "Steffen", "message": "Sabine ist dumm :)" }"#)
+                "56789",
+                None,
+                r#"{ "channel": "Play & Fun", "user_role": "Regular User", "user_id:" "2", "chat_id": "56789", "user": "Steffen", "message": "Sabine ist dumm :)" }"#, None)
             .await;
         let rs3 = moderator
-            .chat_forum(r#"{ "channel": "Play & Fun", "user_role": "Regular User",  "user_id:" "1", "chat_id": "56789", "user": "Sabine", "message": "Steffen du bist selber dumm!" }"#)
+            .chat_forum("56789", None, r#"{ "channel": "Play & Fun", "user_role": "Regular User",  "user_id:" "1", "chat_id": "56789", "user": "Sabine", "message": "Steffen du bist selber dumm!" }"#, None)
             .await;
 
         let rs4 = moderator
-            .chat_forum(r#"{ "channel": "Play & Fun", "user_role": "Regular User", "user_id:" "3", "chat_id": "56789",  "user": "Kevin", "message": "Hallo Kate in welchen Channel sind wir gerade?" }"#)
+            .chat_forum("56789", None, r#"{ "channel": "Play & Fun", "user_role": "Regular User", "user_id:" "3", "chat_id": "56789",  "user": "Kevin", "message": "Hallo Kate in welchen Channel sind wir gerade?" }"#, None)
             .await;
 
         let rs5 = moderator
-            .chat_forum(r#"{ "channel": "Play & Fun", "user_role": "Regular User", "user_id:" "3", "chat_id": "56789", "user": "Kevin", "message": "ich frage mich wo Gerd ist?" }"#)
+            .chat_forum("56789", None, r#"{ "channel": "Play & Fun", "user_role": "Regular User", "user_id:" "3", "chat_id": "56789", "user": "Kevin", "message": "ich frage mich wo Gerd ist?" }"#, None)
             .await;
 
         if let Ok(res) = rs1 {
-            debug!("{}", res);
-            assert_ne!(res, NO_ACTION);
+            debug!("{:?}", res);
+            assert_ne!(res.action, VerdictAction::None);
         }
         if let Ok(res) = rs2 {
-            debug!("{}", res);
-            assert_ne!(res, NO_ACTION);
+            debug!("{:?}", res);
+            assert_ne!(res.action, VerdictAction::None);
         }
         if let Ok(res) = rs3 {
-            debug!("{}", res);
-            assert_ne!(res, NO_ACTION);
+            debug!("{:?}", res);
+            assert_ne!(res.action, VerdictAction::None);
         }
         if let Ok(res) = rs4 {
-            debug!("{}", res);
-            assert_ne!(res, NO_ACTION);
+            debug!("{:?}", res);
+            assert_ne!(res.action, VerdictAction::None);
         }
         if let Ok(res) = rs5 {
-            debug!("{}", res);
-            assert_ne!(res, NO_ACTION);
+            debug!("{:?}", res);
+            assert_ne!(res.action, VerdictAction::None);
         }
     }
 
     #[tokio::test]
     async fn should_test_admin_support_successfully() {
-        let mut moderator =
+        let moderator =
             Moderator::new("Kate", "Kate", &read_prompt_template(MODERATOR_PROMPT_FILE));
         init_logger();
         let channel_id = "Play & Fun";
@@ -353,7 +1238,9 @@ mod moderator_test {
             date_unix_time: Instant::now().elapsed().as_secs().to_string(),
         })
         .unwrap();
-        let _ = moderator.chat_forum(message1.as_str()).await;
+        let _ = moderator
+            .chat_forum("56789", None, message1.as_str(), None)
+            .await;
         message1 = serde_json::to_string(&UserMessage {
             channel: channel_id.to_string(),
             user_role: "Admin".to_string(),
@@ -365,48 +1252,52 @@ mod moderator_test {
             date_unix_time: Instant::now().elapsed().as_secs().to_string(),
         })
         .unwrap();
-        let rs = moderator.chat_forum(message1.as_str()).await;
+        let rs = moderator
+            .chat_forum("56339", None, message1.as_str(), None)
+            .await;
         if let Ok(res) = rs {
-            debug!("{}", res);
+            debug!("{:?}", res);
         }
     }
 
     #[tokio::test]
     async fn should_test_moderator_summerize_chat_successfully() {
-        let mut moderator =
+        let moderator =
             Moderator::new("Kate", "Kate", &read_prompt_template(MODERATOR_PROMPT_FILE));
 
         init_logger();
 
-        let channel_id = "Have Fun";
         let _ = moderator
-            .chat_forum(r#"{ "channel": "Have Fun", "user_role": "Regular User", "user_id:" "1", "chat_id": "12345", "user": "Sabine", "message": "Hallo Leute, gehts euch gut?" }"#)
+            .chat_forum("12345", None, r#"{ "channel": "Have Fun", "user_role": "Regular User", "user_id:" "1", "chat_id": "12345", "user": "Sabine", "message": "Hallo Leute, gehts euch gut?" }"#, None)
             .await;
         let _ = moderator
             .chat_forum(
+                "12345",
+                None,
                 r#"{ "channel": "Have Fun", "user_role": "Regular User", "user_id:" "2", "chat_id": "12345", "user": "Kevin", "message": "Jau alles bestens" }"#,
+                None,
             )
             .await;
         let _ = moderator
-            .chat_forum(r#"{ "channel": "Have Fun", "user_role": "Regular User", "user_id:" "3", "chat_id": "12345", "user": "Steffi", "message": "Wo ist Steffen in letzter Zeit?" }"#)
+            .chat_forum("12345", None, r#"{ "channel": "Have Fun", "user_role": "Regular User", "user_id:" "3", "chat_id": "12345", "user": "Steffi", "message": "Wo ist Steffen in letzter Zeit?" }"#, None)
             .await;
         let _ = moderator
-            .chat_forum(r#"{ "channel": "Have Fun", "user_role": "Regular User", "user_id:" "1", "chat_id": "12345", "user": "Sabine", "message": "Keine Ahnung wo er steck" }"#)
+            .chat_forum("12345", None, r#"{ "channel": "Have Fun", "user_role": "Regular User", "user_id:" "1", "chat_id": "12345", "user": "Sabine", "message": "Keine Ahnung wo er steck" }"#, None)
             .await;
         let _ = moderator
-            .chat_forum(r#"{ "channel": "Have Fun", "user_role": "Regular User", "user_id:" "2", "chat_id": "12345",  "user": "Kevin", "message": "Der hat Urlaub gerade auf der Karibik hehe :)" }"#)
+            .chat_forum("12345", None, r#"{ "channel": "Have Fun", "user_role": "Regular User", "user_id:" "2", "chat_id": "12345",  "user": "Kevin", "message": "Der hat Urlaub gerade auf der Karibik hehe :)" }"#, None)
             .await;
         let _ = moderator
-            .chat_forum(r#"{ "channel": "Have Fun", "user_role": "Regular User", "user_id:" "1", "chat_id": "12345",  "user": "Sabine", "message": "Schön da möchte ich auch mal hin" }"#)
+            .chat_forum("12345", None, r#"{ "channel": "Have Fun", "user_role": "Regular User", "user_id:" "1", "chat_id": "12345",  "user": "Sabine", "message": "Schön da möchte ich auch mal hin" }"#, None)
             .await;
         let _ = moderator
-            .chat_forum(r#"{ "channel": "Cloud Stuffs", "user_role": "Regular User", "user_id:" "4", "chat_id": "4321",  "user": "Conrad", "message": "Was passiert gerade in der Cloud?" }"#)
+            .chat_forum("4321", None, r#"{ "channel": "Cloud Stuffs", "user_role": "Regular User", "user_id:" "4", "chat_id": "4321",  "user": "Conrad", "message": "Was passiert gerade in der Cloud?" }"#, None)
             .await;
         let _ = moderator
-            .chat_forum(r#"{ "channel": "Cloud Stuffs", "user_role": "Regular User", "user_id:" "5", "chat_id": "4321", "user": "Morice", "message": "Keine Ahnung, wahrscheinlich gab es dort einen update" }"#)
+            .chat_forum("4321", None, r#"{ "channel": "Cloud Stuffs", "user_role": "Regular User", "user_id:" "5", "chat_id": "4321", "user": "Morice", "message": "Keine Ahnung, wahrscheinlich gab es dort einen update" }"#, None)
             .await;
 
-        let rs = moderator.summarize_chat(channel_id).await;
+        let rs = moderator.summarize_chat("12345", None, None).await;
         if let Ok(res) = rs {
             debug!("{}", res);
             assert!(!res.contains("Cloud") && !res.contains("update"));
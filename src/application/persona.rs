@@ -0,0 +1,172 @@
+use anyhow::anyhow;
+
+use crate::adapter::PersonaEntity;
+use crate::application::{validate_prompt_template, Locale};
+
+pub const PERSONA_FIELDS: [&str; 5] = ["name", "rules", "language", "strictness", "template"];
+const STRICTNESS_LEVELS: [&str; 3] = ["lenient", "normal", "strict"];
+
+/// Changes a persona setting of an admin command, an empty value restores the bot-wide setting.
+pub fn set_persona_field(
+    persona: &mut PersonaEntity,
+    field: &str,
+    value: &str,
+) -> Result<(), anyhow::Error> {
+    let value_opt: Option<String> =
+        Some(value.trim().to_string()).filter(|value| !value.is_empty());
+    match field {
+        "name" => persona.name = value_opt,
+        "rules" => persona.rules = value_opt,
+        "language" => {
+            let locale_opt: Option<Locale> = match &value_opt {
+                Some(language) => Some(Locale::from_code(language).ok_or_else(|| {
+                    anyhow!(
+                        "Unknown language {}, use one of {}",
+                        language,
+                        Locale::ALL.map(|locale| locale.code()).join(", ")
+                    )
+                })?),
+                None => None,
+            };
+            persona.language = locale_opt.map(|locale| locale.code().to_string());
+        }
+        "strictness" => {
+            let strictness_opt: Option<String> = value_opt.map(|value| value.to_lowercase());
+            if let Some(strictness) = &strictness_opt {
+                if !STRICTNESS_LEVELS.contains(&strictness.as_str()) {
+                    return Err(anyhow!(
+                        "Unknown strictness {}, use one of {}",
+                        strictness,
+                        STRICTNESS_LEVELS.join(", ")
+                    ));
+                }
+            }
+            persona.strictness = strictness_opt;
+        }
+        "template" => {
+            if let Some(template) = &value_opt {
+                validate_prompt_template(template)?;
+            }
+            persona.prompt_template = value_opt;
+        }
+        _ => {
+            return Err(anyhow!(
+                "Unknown persona setting {}, use one of {}",
+                field,
+                PERSONA_FIELDS.join(", ")
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Runs a persona which didn't come from `/persona`, e.g. an imported one, through the same
+/// checks and returns it with its settings normalized.
+pub fn validate_persona(persona: &PersonaEntity) -> Result<PersonaEntity, anyhow::Error> {
+    let mut validated = PersonaEntity::default();
+    for (field, value_opt) in [
+        ("name", &persona.name),
+        ("rules", &persona.rules),
+        ("language", &persona.language),
+        ("strictness", &persona.strictness),
+        ("template", &persona.prompt_template),
+    ] {
+        if let Some(value) = value_opt {
+            set_persona_field(&mut validated, field, value)?;
+        }
+    }
+    Ok(validated)
+}
+
+/// Persona settings of a chat as listed to admins.
+pub fn describe_persona(persona: &PersonaEntity) -> String {
+    let setting = |value: &Option<String>| value.clone().unwrap_or(String::from("(default)"));
+    format!(
+        "name: {}\nrules: {}\nlanguage: {}\nstrictness: {}\ntemplate: {}",
+        setting(&persona.name),
+        setting(&persona.rules),
+        setting(&persona.language),
+        setting(&persona.strictness),
+        persona
+            .prompt_template
+            .as_ref()
+            .map_or(String::from("(default)"), |template| format!(
+                "custom, {} characters",
+                template.chars().count()
+            ))
+    )
+}
+
+/// Instructions appended to the prompt template of a chat with a persona.
+pub fn persona_prompt_section(persona: &PersonaEntity) -> String {
+    let mut instructions: Vec<String> = Vec::new();
+    if let Some(rules) = &persona.rules {
+        instructions.push(format!(
+            "- The group rules for Regular Users in this chat replace the group rules above:\n{}",
+            rules.trim()
+        ));
+    }
+    if let Some(locale) = persona.language.as_deref().and_then(Locale::from_code) {
+        instructions.push(format!(
+            "- Always interact in {}, regardless of the language named above.",
+            locale.language_name()
+        ));
+    }
+    match persona.strictness.as_deref() {
+        Some("lenient") => instructions.push(String::from(
+            "- Be lenient: only warn for clear and severe rule violations, otherwise prefer a friendly reply.",
+        )),
+        Some("strict") => instructions.push(String::from(
+            "- Be strict: warn for every rule violation and escalate repeated or severe violations to the Admins.",
+        )),
+        _ => {}
+    }
+    if instructions.is_empty() {
+        return String::new();
+    }
+    format!("## Persona in this chat\n\n{}", instructions.join("\n"))
+}
+
+#[cfg(test)]
+mod persona_test {
+    use crate::adapter::PersonaEntity;
+    use crate::application::persona::{
+        persona_prompt_section, set_persona_field, validate_persona,
+    };
+
+    #[test]
+    fn should_validate_persona_settings() {
+        let mut persona = PersonaEntity::default();
+
+        set_persona_field(&mut persona, "language", "English").unwrap();
+        assert_eq!(persona.language.as_deref(), Some("en"));
+        assert!(set_persona_field(&mut persona, "language", "Klingon").is_err());
+        set_persona_field(&mut persona, "strictness", "Strict").unwrap();
+        assert!(set_persona_field(&mut persona, "strictness", "brutal").is_err());
+        assert!(set_persona_field(&mut persona, "template", "You are {name}").is_err());
+        assert!(set_persona_field(&mut persona, "mood", "happy").is_err());
+
+        assert_eq!(persona.strictness.as_deref(), Some("strict"));
+        assert!(persona_prompt_section(&persona).contains("Always interact in English"));
+        set_persona_field(&mut persona, "language", "").unwrap();
+        assert_eq!(persona.language, None);
+    }
+
+    #[test]
+    fn should_reject_imported_persona_with_invalid_template() {
+        let persona = PersonaEntity {
+            strictness: Some("Lenient".to_string()),
+            ..PersonaEntity::default()
+        };
+        assert_eq!(
+            validate_persona(&persona).unwrap().strictness.as_deref(),
+            Some("lenient")
+        );
+
+        let persona = PersonaEntity {
+            prompt_template: Some("You are {name}".to_string()),
+            ..persona
+        };
+        assert!(validate_persona(&persona).is_err());
+    }
+}
@@ -6,7 +6,7 @@ use schemars::JsonSchema;
 use serde::Deserialize;
 use serde_json::Value;
 
-use crate::UserManagement;
+use crate::{application::tools::ToolOutcome, UserManagement};
 
 pub const KICK_USER_WITHOUTBAN: &str = "kick_user_from_chat";
 pub const KICK_USER_WITHOUTBAN_DESCRIPTION: &str = "Kicks a user from the telegram chat.";
@@ -25,30 +25,24 @@ pub struct KickUserWithoutBan {
     user_management: UserManagement,
 }
 
-impl Default for KickUserWithoutBan {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
 impl KickUserWithoutBan {
-    pub fn new() -> Self {
+    pub fn new(user_management: UserManagement) -> Self {
         let client = Client::new(env::var("TELEGRAM_TOKEN").unwrap());
         Self {
             telegram_api: mobot::api::API::new(client),
-            user_management: UserManagement::new(),
+            user_management,
         }
     }
 
     pub async fn execute(
         &self,
         params: Value,
-    ) -> std::result::Result<String, Box<dyn std::error::Error + Sync + Send>> {
+    ) -> std::result::Result<ToolOutcome, Box<dyn std::error::Error + Sync + Send>> {
         let parameters = serde_json::from_value::<KickUserParams>(params)?;
 
         let user_opt = self
             .user_management
-            .get_user_by_first_name(&parameters.name);
+            .get_user_by_first_name(&parameters.chat_id.to_string(), &parameters.name);
         let user_id_be_ban: i64 = if let Some((_, user)) = user_opt {
             user.user_id
         } else {
@@ -56,7 +50,10 @@ impl KickUserWithoutBan {
                 "User not found in memory: username={}, chat_id={}",
                 parameters.name, parameters.chat_id
             );
-            return Ok("Could not ban user, because user has never contribute in the chat".into());
+            return Ok(String::from(
+                "Could not ban user, because user has never contribute in the chat",
+            )
+            .into());
         };
 
         let success_rs = self
@@ -69,11 +66,14 @@ impl KickUserWithoutBan {
             ))
             .await?;
         if !success_rs {
-            return Ok("Failed to kick the member".into());
+            return Ok(String::from("Failed to kick the member").into());
         }
-        Ok(format!(
-            "Member {} has been kicked from chat {}",
-            parameters.name, parameters.chat_id
-        ))
+        Ok(ToolOutcome {
+            result: format!(
+                "Member {} has been kicked from chat {}",
+                parameters.name, parameters.chat_id
+            ),
+            sanctioned_user_id: Some(user_id_be_ban),
+        })
     }
 }
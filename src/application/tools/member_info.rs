@@ -1,6 +1,9 @@
 use std::env;
 
-use crate::UserManagement;
+use crate::{
+    adapter::{SanctionEntity, UserEntity},
+    UserManagement,
+};
 use log::debug;
 use mobot::{
     api::{ChatMember, GetChatMemberRequest},
@@ -11,7 +14,9 @@ use serde::Deserialize;
 use serde_json::{json, Value};
 
 pub const MEMBER_INFO: &str = "get_member_info";
-pub const MEMBER_INFO_DESCRIPTION: &str = "Get information of a chat member.";
+pub const MEMBER_INFO_DESCRIPTION: &str =
+    "Get information of a chat member, including activity, past warnings, mutes, kicks and previous names.";
+const MAX_RECENT_SANCTIONS: usize = 5;
 
 #[derive(Deserialize, JsonSchema)]
 pub struct MemberInfoParam {
@@ -28,11 +33,11 @@ pub struct GetMember {
 }
 
 impl GetMember {
-    pub fn new() -> Self {
+    pub fn new(user_management: UserManagement) -> Self {
         let client = Client::new(env::var("TELEGRAM_TOKEN").unwrap());
         Self {
             telegram_api: mobot::api::API::new(client),
-            user_management: UserManagement::new(),
+            user_management,
         }
     }
 
@@ -43,10 +48,10 @@ impl GetMember {
         let parameters = serde_json::from_value::<MemberInfoParam>(params)?;
         let user_opt = self
             .user_management
-            .get_user_by_first_name(&parameters.name);
+            .get_user_by_first_name(&parameters.chat_id, &parameters.name);
 
-        let user_id: i64 = if let Some((_, user)) = user_opt {
-            user.user_id
+        let user: UserEntity = if let Some((_, user)) = user_opt {
+            user
         } else {
             debug!(
                 "User not found in memory: firstname={}, chat_id={}",
@@ -57,7 +62,7 @@ impl GetMember {
 
         let mem_info: ChatMember = self
             .telegram_api
-            .get_chat_member(&GetChatMemberRequest::new(parameters.chat_id, user_id))
+            .get_chat_member(&GetChatMemberRequest::new(parameters.chat_id, user.user_id))
             .await?;
 
         let current_member = if let Some(current_member) = mem_info.is_member {
@@ -93,6 +98,20 @@ impl GetMember {
             "last_name": last_name,
             "username": mem_info.user.username,
             "current_chat_member": current_member,
+            "first_seen_unix_time": user.first_seen_unix_time,
+            "last_activity_unix_time": user.last_activity_unix_time,
+            "message_count": user.message_count,
+            "message_count_per_topic": user.topic_message_counts,
+            "warning_count": user.count_sanctions("warning"),
+            "mute_count": user.count_sanctions("mute"),
+            "kick_count": user.count_sanctions("kick"),
+            "recent_sanctions": user
+                .sanctions
+                .iter()
+                .rev()
+                .take(MAX_RECENT_SANCTIONS)
+                .collect::<Vec<&SanctionEntity>>(),
+            "previous_names": user.name_history,
         })
         .to_string())
     }
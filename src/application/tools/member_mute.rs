@@ -9,7 +9,7 @@ use schemars::JsonSchema;
 use serde::Deserialize;
 use serde_json::Value;
 
-use crate::UserManagement;
+use crate::{application::tools::ToolOutcome, UserManagement};
 
 pub const MUTE_MEMBER: &str = "mute_member_in_chat";
 pub const MUTE_MEMBER_DESCRIPTION: &str = "Mute the user from the telegram chat.";
@@ -30,30 +30,24 @@ pub struct MuteMember {
     telegram_api: mobot::api::API,
     user_management: UserManagement,
 }
-impl Default for MuteMember {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
 impl MuteMember {
-    pub fn new() -> Self {
+    pub fn new(user_management: UserManagement) -> Self {
         let client = Client::new(env::var("TELEGRAM_TOKEN").unwrap());
         Self {
             telegram_api: mobot::api::API::new(client),
-            user_management: UserManagement::new(),
+            user_management,
         }
     }
 
     pub async fn execute(
         &self,
         params: Value,
-    ) -> std::result::Result<String, Box<dyn std::error::Error + Sync + Send>> {
+    ) -> std::result::Result<ToolOutcome, Box<dyn std::error::Error + Sync + Send>> {
         let parameters = serde_json::from_value::<MuteMemberParams>(params)?;
 
         let user_opt = self
             .user_management
-            .get_user_by_first_name(&parameters.name);
+            .get_user_by_first_name(&parameters.chat_id.to_string(), &parameters.name);
 
         let user_id_be_muted: i64 = if let Some((_, user)) = user_opt {
             user.user_id
@@ -62,7 +56,10 @@ impl MuteMember {
                 "User not found in memory: firstname={}, chat_id={}",
                 parameters.name, parameters.chat_id
             );
-            return Ok("Could not mute user, because user has never contribute in the chat".into());
+            return Ok(String::from(
+                "Could not mute user, because user has never contribute in the chat",
+            )
+            .into());
         };
 
         let chat_id = parameters.chat_id;
@@ -101,8 +98,11 @@ impl MuteMember {
                 "Failed to mute member: user_id={}, chat_id={}, mute_time={}",
                 user_id_be_muted, chat_id, mute_time_seconds
             );
-            return Ok("Failed to mute the member".into());
+            return Ok(String::from("Failed to mute the member").into());
         }
-        Ok("Member muted successfully".into())
+        Ok(ToolOutcome {
+            result: "Member muted successfully".to_string(),
+            sanctioned_user_id: Some(user_id_be_muted),
+        })
     }
 }
@@ -27,30 +27,70 @@ pub use websearch::WEB_SEARCH_DESCRIPTION;
 use serde_json::Value;
 use std::error::Error;
 
+use crate::UserManagement;
+
+/// Tools which act on chat members or messages, their calls are recorded in the audit log.
+pub fn is_moderation_tool(tool_name: &str) -> bool {
+    matches!(
+        tool_name,
+        KICK_USER_WITHOUTBAN | MUTE_MEMBER | MESSAGE_REACTION
+    )
+}
+
+/// Kind of sanction a tool call imposes on a member, recorded in the member profile.
+pub fn sanction_kind_of(tool_name: &str) -> Option<&'static str> {
+    match tool_name {
+        KICK_USER_WITHOUTBAN => Some("kick"),
+        MUTE_MEMBER => Some("mute"),
+        _ => None,
+    }
+}
+
+/// Result of a tool call which is reported back to the LLM.
+pub struct ToolOutcome {
+    pub result: String,
+    /// Member on whom the tool has imposed its sanction, only set if the sanction took effect.
+    pub sanctioned_user_id: Option<i64>,
+}
+
+impl From<String> for ToolOutcome {
+    fn from(result: String) -> Self {
+        Self {
+            result,
+            sanctioned_user_id: None,
+        }
+    }
+}
+
+/// Executes a tool call of the LLM, member tools look up members in `user_management`.
 pub async fn execute_tool(
     tool_name: &str,
     parameters: Value,
-) -> std::result::Result<String, Box<dyn Error + Sync + Send>> {
+    user_management: &UserManagement,
+) -> std::result::Result<ToolOutcome, Box<dyn Error + Sync + Send>> {
     match tool_name {
         WEB_SEARCH => {
             let mut websearch = WebSearch::new();
-            websearch.execute(parameters).await
+            websearch.execute(parameters).await.map(ToolOutcome::from)
         }
         KICK_USER_WITHOUTBAN => {
-            let kick_user = KickUserWithoutBan::new();
+            let kick_user = KickUserWithoutBan::new(user_management.clone());
             kick_user.execute(parameters).await
         }
         MUTE_MEMBER => {
-            let mute_member = MuteMember::new();
+            let mute_member = MuteMember::new(user_management.clone());
             mute_member.execute(parameters).await
         }
         MEMBER_INFO => {
-            let member_info = GetMember::new();
-            member_info.execute(parameters).await
+            let member_info = GetMember::new(user_management.clone());
+            member_info.execute(parameters).await.map(ToolOutcome::from)
         }
         MESSAGE_REACTION => {
             let message_reaction = MessageReaction::new();
-            message_reaction.execute(parameters).await
+            message_reaction
+                .execute(parameters)
+                .await
+                .map(ToolOutcome::from)
         }
         _ => Err("Tool not found".into()),
     }
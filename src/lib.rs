@@ -2,13 +2,24 @@ mod adapter;
 mod application;
 pub use adapter::bot_greeting_action;
 pub use adapter::chat_summarize_action;
+pub use adapter::export_action;
+pub use adapter::flush_pending_writes;
 pub use adapter::handle_chat_messages;
+pub use adapter::import_action;
+pub use adapter::import_json_database;
 pub use adapter::inactive_users_action;
 pub use adapter::init_bot;
 pub use adapter::killswitch_action;
+pub use adapter::modlog_action;
 pub use adapter::mute_user_action;
+pub use adapter::persona_action;
+pub use adapter::register_bot_commands;
+pub use adapter::reload_prompt_action;
+pub use adapter::remember_action;
 pub use adapter::unmute_user_action;
+pub use adapter::why_action;
 pub use adapter::BotController;
+pub use application::read_prompt_template;
 pub use application::Moderator;
 pub use application::UserManagement;
 pub use application::MODERATOR_PROMPT_FILE;
@@ -1,51 +1,42 @@
 use ferrisbot::{BotController, MODERATOR_PROMPT_FILE};
-use mobot::{api::BotCommand, Client, Matcher, Route, Router};
-use std::{env, fs::read_to_string};
-
-fn read_prompt_template(path: &str) -> String {
-    let template = read_to_string(path);
-    match template {
-        Ok(content) => content,
-        Err(e) => {
-            panic!("Failed to read the prompt template file: {}", e);
-        }
-    }
-}
+use mobot::{Client, Matcher, Route, Router};
+use std::env;
+use tokio::signal::unix::{signal, SignalKind};
 
 #[tokio::main]
 async fn main() {
     dotenvy::dotenv().ok();
     mobot::init_logger();
+    if env::args().nth(1).as_deref() == Some("import-json") {
+        let json_file_path = env::args()
+            .nth(2)
+            .unwrap_or_else(|| "./bot_db.json".to_string());
+        match ferrisbot::import_json_database(&json_file_path) {
+            Ok(user_count) => log::info!(
+                "Imported {} users from {} into the configured database",
+                user_count,
+                json_file_path
+            ),
+            Err(e) => log::error!("Failed to import {}: {}", json_file_path, e),
+        }
+        return;
+    }
     let bot_name = env::var("BOT_NAME").unwrap_or_else(|_| "Ferrisbot".to_string());
     let bot_username = env::var("BOT_USERNAME").unwrap_or_else(|_| "Ferrisbot".to_string());
-    let commands = vec![
-        BotCommand {
-            command: "greeting".into(),
-            description: "Begrüß die Gruppe".into(),
-        },
-        BotCommand {
-            command: "summary".into(),
-            description: "Gib eine Zusammenfassung der letzten Chatverlauf".into(),
-        },
-    ];
     let client = Client::new(
         env::var("TELEGRAM_TOKEN").expect("TELEGRAM_TOKEN environment variable not set"),
     );
-    let controller = BotController::new(
-        &bot_name,
-        &bot_username,
-        &read_prompt_template(MODERATOR_PROMPT_FILE),
-    );
+    let prompt_template = match ferrisbot::read_prompt_template(MODERATOR_PROMPT_FILE) {
+        Ok(prompt_template) => prompt_template,
+        Err(e) => {
+            log::error!("{}", e);
+            return;
+        }
+    };
+    let controller = BotController::new(&bot_name, &bot_username, &prompt_template);
     let mut router: mobot::Router<BotController> = Router::new(client).with_state(controller);
 
-    router
-        .api
-        .set_my_commands(&mobot::api::SetMyCommandsRequest {
-            commands,
-            ..Default::default()
-        })
-        .await
-        .unwrap();
+    ferrisbot::register_bot_commands(&router.api).await.unwrap();
 
     router
         .add_route(
@@ -72,6 +63,34 @@ async fn main() {
             Route::Message(Matcher::BotCommand(String::from("inactiveUsers"))),
             ferrisbot::inactive_users_action,
         )
+        .add_route(
+            Route::Message(Matcher::BotCommand(String::from("modlog"))),
+            ferrisbot::modlog_action,
+        )
+        .add_route(
+            Route::Message(Matcher::BotCommand(String::from("remember"))),
+            ferrisbot::remember_action,
+        )
+        .add_route(
+            Route::Message(Matcher::BotCommand(String::from("export"))),
+            ferrisbot::export_action,
+        )
+        .add_route(
+            Route::Message(Matcher::BotCommand(String::from("import"))),
+            ferrisbot::import_action,
+        )
+        .add_route(
+            Route::Message(Matcher::BotCommand(String::from("persona"))),
+            ferrisbot::persona_action,
+        )
+        .add_route(
+            Route::Message(Matcher::BotCommand(String::from("reloadprompt"))),
+            ferrisbot::reload_prompt_action,
+        )
+        .add_route(
+            Route::Message(Matcher::BotCommand(String::from("why"))),
+            ferrisbot::why_action,
+        )
         .add_route(
             Route::Message(Matcher::BotCommand(String::from("killswitch"))),
             ferrisbot::killswitch_action,
@@ -82,5 +101,11 @@ async fn main() {
             ferrisbot::handle_chat_messages,
         );
 
-    router.start().await;
+    let mut terminate = signal(SignalKind::terminate()).expect("SIGTERM handler not installable");
+    tokio::select! {
+        _ = router.start() => {}
+        _ = tokio::signal::ctrl_c() => log::info!("Shutdown signal received"),
+        _ = terminate.recv() => log::info!("Termination signal received"),
+    }
+    ferrisbot::flush_pending_writes().await;
 }